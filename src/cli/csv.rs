@@ -1,76 +1,1107 @@
 use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
 use std::str::FromStr;
 
 use clap::ArgAction::SetFalse;
-use clap::Parser;
+use clap::{ArgGroup, Args, Parser, Subcommand};
+use indexmap::IndexMap;
 use serde::{Deserialize, Serialize};
 
+use crate::cli::fake::FieldKind;
 use crate::utils::{verify_file_exists, verify_format_valid};
 #[derive(Parser, Debug)]
+#[command(args_conflicts_with_subcommands = true)]
 pub struct CsvOpts {
-    // this value were use like "input.csv".into() to convert it into a String
-    #[arg(short,long,default_value="input.csv",value_parser=verify_file_exists)]
+    #[command(subcommand)]
+    pub cmd: Option<CsvSubcommand>,
+
+    /// 输入文件，未指定时读取 input.csv；`-` 表示标准输入
+    // 不使用 default_value，以免执行子命令时也去校验默认文件是否存在
+    #[arg(short, long, value_parser = verify_file_exists)]
+    pub input: Option<String>,
+    /// 输出文件路径，`-` 表示写到标准输出；未指定时按输出格式写到 output.json、output.yaml 等
+    #[arg(short, long)]
+    pub output: Option<String>,
+
+    /// 输出格式，未指定时按 --output 的扩展名推断，无法推断或没有 --output 时使用 json
+    #[arg(long,value_parser=verify_format_valid)]
+    pub format: Option<String>,
+
+    /// 输入格式，未指定时按输入文件扩展名判断
+    /// （.json/.yaml/.yml/.toml，.xlsx/.xlsm/.xlsb/.xls/.ods 为表格，其余按 csv）
+    #[arg(long)]
+    pub from: Option<InputFormat>,
+    /// 读取表格时使用的工作表，可以是名称或从 1 开始的序号，默认第一个工作表
+    #[arg(long)]
+    pub sheet: Option<String>,
+    /// 读取表格时的单元格区域，例如 A1:F100；只给左上角（如 B3）时读到数据末尾
+    #[arg(long)]
+    pub range: Option<CellRange>,
+    /// 输出压缩方式（gzip、zstd），未指定时按 --output 的 .gz/.zst 后缀判断；
+    /// 输入按文件内容自动识别 gzip/zstd 并解压
+    #[arg(long)]
+    pub compress: Option<Compression>,
+    /// 输出编码：utf-8、gbk、utf-16le、latin1，默认 utf-8；utf-16le 会写入 BOM。
+    /// 例如把 JSON 转回 CSV 给只认 GBK 的 Excel 打开
+    #[arg(long)]
+    pub output_encoding: Option<TextEncoding>,
+
+    #[command(flatten)]
+    pub read: CsvReadArgs,
+
+    /// 在 stderr 输出读取/写出的行数和耗时
+    #[arg(short, long)]
+    pub verbose: bool,
+
+    /// TOML 输出的根键；读取 JSON/YAML/TOML 时也从该键下取记录数组
+    #[arg(long, alias = "root-key", default_value = "items")]
+    pub toml_root_key: String,
+    /// XML 输出的根元素名
+    #[arg(long, default_value = "rows")]
+    pub xml_root: String,
+    /// XML 输出中每条记录的元素名
+    #[arg(long, default_value = "row")]
+    pub xml_row: String,
+
+    /// 按字母顺序输出字段，默认保持 CSV 表头的列顺序
+    #[arg(long)]
+    pub sort_keys: bool,
+
+    /// 只输出满足条件的行，例如 --where 'gender == "Female" && id > 10'，
+    /// 支持 == != < <= > >= ~(正则) && || ! 以及 is_empty(col)、len(col)、contains(col, "x") 等函数
+    #[arg(long = "where", value_name = "EXPR")]
+    pub r#where: Option<String>,
+    /// 按列排序，`-` 前缀表示降序，例如 --sort-by city,-id；两边都是数字时按数值比较
+    #[arg(long, value_delimiter = ',', allow_hyphen_values = true)]
+    pub sort_by: Vec<String>,
+    /// 排序时内存中最多缓存的行数，超出后分块写入临时文件再归并
+    #[arg(long, default_value_t = 100_000)]
+    pub sort_buffer_rows: usize,
+    /// 按这些列去重，只保留第一次出现的行，例如 --dedupe-by email
+    #[arg(long, value_delimiter = ',')]
+    pub dedupe_by: Vec<String>,
+
+    /// 只输出这些列，并按给定顺序排列，例如 --select id,email,city
+    #[arg(long, value_delimiter = ',')]
+    pub select: Option<Vec<String>>,
+    /// 不输出这些列
+    #[arg(long, value_delimiter = ',')]
+    pub exclude: Vec<String>,
+    /// 重命名输出列（使用原始列名），例如 --rename email=contact
+    #[arg(long, value_delimiter = ',', value_parser = parse_rename)]
+    pub rename: Vec<(String, String)>,
+
+    /// 指定列类型（使用重命名后的列名），覆盖自动推断，例如 --types id=int,price=float
+    #[arg(long, value_delimiter = ',', value_parser = parse_type_override)]
+    pub types: Vec<(String, ColumnType)>,
+    /// 关闭类型推断，未在 --types 中指定的列都按字符串输出
+    #[arg(long)]
+    pub no_infer: bool,
+    /// 用于推断列类型的采样行数
+    #[arg(long, default_value_t = 1000)]
+    pub infer_rows: usize,
+    /// 按内置的记录结构体反序列化每一行（目前支持 person），字段类型由结构体决定，
+    /// 解析失败时报告输出记录的序号和列名（parse output record:N），经过 --where、--sort-by
+    /// 等处理后该序号不等于文件中的行号
+    #[arg(long, conflicts_with_all = ["types", "no_infer", "unflatten"])]
+    pub profile: Option<RecordProfile>,
+
+    /// 按列脱敏（使用原始列名），例如
+    /// --mask email=hash,ip_address=truncate/24,last_name=redact,first_name=fake；
+    /// 规则：hash[/N]、truncate[/N]、redact、fake[/kind]
+    #[arg(long, value_delimiter = ',', value_parser = parse_mask)]
+    pub mask: Vec<(String, MaskRule)>,
+    /// hash 和 fake 规则使用的 BLAKE3 密钥文件（32 字节），可由
+    /// text generate-key-pair --output-path <目录> 生成，文件名为 blake3.key；
+    /// 同一个密钥在不同文件中产生相同的假名
+    #[arg(long, value_parser = verify_file_exists)]
+    pub mask_key: Option<String>,
+
+    /// 按列名还原嵌套结构，例如 address.city 还原为对象、tags[0] 还原为数组
+    #[arg(long)]
+    pub unflatten: bool,
+    /// 嵌套列名中的键分隔符，读取 JSON/YAML/TOML 展开嵌套对象时也使用它
+    #[arg(long, default_value = ".")]
+    pub separator: String,
+}
+
+impl CsvOpts {
+    pub fn input(&self) -> &str {
+        self.input.as_deref().unwrap_or("input.csv")
+    }
+
+    /// 输出文件路径，未指定 --output 时使用 output.<格式的扩展名>
+    pub fn output(&self, format: OutputFormat) -> String {
+        match &self.output {
+            Some(output) => output.clone(),
+            None => format!("output.{}", format.extension()),
+        }
+    }
+}
+
+/// `csv` 下的分析类子命令；不带子命令时执行格式转换
+#[derive(Subcommand, Debug)]
+pub enum CsvSubcommand {
+    #[command(about = "Profile every column of a CSV file")]
+    Stats(StatsOpts),
+    #[command(about = "Group rows and compute aggregates such as count, avg(id), max(id)")]
+    Aggregate(AggregateOpts),
+    #[command(about = "Build a pivot table: one row per --rows key, one column per --cols value")]
+    Pivot(PivotOpts),
+    #[command(about = "Run a SQL query over CSV files, each file is a table named after its stem")]
+    Query(QueryOpts),
+    #[command(about = "Join two CSV files on key columns")]
+    Join(JoinOpts),
+    #[command(about = "Compare two CSV files by key and report added, removed and modified rows")]
+    Diff(DiffOpts),
+    #[command(about = "Validate a CSV file against a TOML schema or JSON Schema")]
+    Validate(ValidateOpts),
+    #[command(
+        about = "Infer a schema from a CSV file and emit it as JSON Schema, TOML or a Rust struct"
+    )]
+    Schema(SchemaOpts),
+    #[command(about = "Split a CSV file into chunks of N rows or one file per column value")]
+    Split(SplitOpts),
+    #[command(about = "Concatenate CSV files, reconciling their headers")]
+    Cat(CatOpts),
+}
+
+/// 子命令共用的输出参数，写出时复用 csv 转换的各个格式
+#[derive(Args, Debug, Clone)]
+pub struct CsvWriteArgs {
+    /// 输出文件路径，`-` 表示写到标准输出
+    #[arg(short, long, default_value = "-")]
+    pub output: String,
+    /// 输出格式，未指定时按 --output 的扩展名推断，无法推断时使用 json
+    #[arg(long, value_parser = verify_format_valid)]
+    pub format: Option<String>,
+    /// 输出压缩方式（gzip、zstd），未指定时按 --output 的 .gz/.zst 后缀判断
+    #[arg(long)]
+    pub compress: Option<Compression>,
+    /// 输出编码：utf-8、gbk、utf-16le、latin1，默认 utf-8；utf-16le 会写入 BOM
+    #[arg(long)]
+    pub output_encoding: Option<TextEncoding>,
+    /// TOML 输出的根键
+    #[arg(long, alias = "root-key", default_value = "items")]
+    pub toml_root_key: String,
+    /// XML 输出的根元素名
+    #[arg(long, default_value = "rows")]
+    pub xml_root: String,
+    /// XML 输出中每条记录的元素名
+    #[arg(long, default_value = "row")]
+    pub xml_row: String,
+}
+
+#[derive(Parser, Debug)]
+pub struct AggregateOpts {
+    #[arg(value_parser = verify_file_exists)]
+    pub input: String,
+    #[command(flatten)]
+    pub read: CsvReadArgs,
+    #[command(flatten)]
+    pub write: CsvWriteArgs,
+    /// 分组列，不指定时把所有行当作一组
+    #[arg(long, value_delimiter = ',')]
+    pub group_by: Vec<String>,
+    /// 聚合函数：count、count(col)、sum、avg、min、max、median、distinct、first、last，
+    /// 例如 --agg count,avg(id),max(id)
+    #[arg(long, value_delimiter = ',', required = true)]
+    pub agg: Vec<Aggregation>,
+    /// 先过滤再聚合，语法与 csv --where 相同
+    #[arg(long = "where", value_name = "EXPR")]
+    pub r#where: Option<String>,
+}
+
+#[derive(Parser, Debug)]
+pub struct PivotOpts {
+    #[arg(value_parser = verify_file_exists)]
     pub input: String,
-    #[arg(short, long, default_value = "output.json")]
+    #[command(flatten)]
+    pub read: CsvReadArgs,
+    #[command(flatten)]
+    pub write: CsvWriteArgs,
+    /// 作为行的列，每组取值输出一行
+    #[arg(long, value_delimiter = ',', required = true)]
+    pub rows: Vec<String>,
+    /// 该列的每个不同取值成为一个输出列
+    #[arg(long)]
+    pub cols: String,
+    /// 被聚合的列，--agg count 时可省略
+    #[arg(long)]
+    pub value: Option<String>,
+    /// 单元格使用的聚合函数名，例如 count、sum、avg
+    #[arg(long, default_value = "count")]
+    pub agg: AggFunc,
+    /// 没有数据的单元格填充的值
+    #[arg(long, default_value = "")]
+    pub fill: String,
+    /// 先过滤再透视，语法与 csv --where 相同
+    #[arg(long = "where", value_name = "EXPR")]
+    pub r#where: Option<String>,
+}
+
+#[derive(Parser, Debug)]
+pub struct QueryOpts {
+    /// SQL 语句（SQLite 语法），例如 "SELECT car, count(*) FROM input GROUP BY car ORDER BY 2 DESC"
+    pub sql: String,
+    /// 作为表的 CSV 文件，表名为文件名去掉扩展名，例如 input.csv -> input
+    #[arg(required = true, value_parser = verify_file_exists)]
+    pub inputs: Vec<String>,
+    #[command(flatten)]
+    pub read: CsvReadArgs,
+    #[command(flatten)]
+    pub write: CsvWriteArgs,
+    /// 建表时不推断列类型，所有列都按 TEXT 存储
+    #[arg(long)]
+    pub no_infer: bool,
+}
+
+#[derive(Parser, Debug)]
+pub struct JoinOpts {
+    #[arg(value_parser = verify_file_exists)]
+    pub left: String,
+    #[arg(value_parser = verify_file_exists)]
+    pub right: String,
+    /// 两边同名的键列，例如 --on id
+    #[arg(long, value_delimiter = ',', conflicts_with_all = ["left_on", "right_on"])]
+    pub on: Option<Vec<String>>,
+    /// 左表的键列，需与 --right-on 一一对应
+    #[arg(long, value_delimiter = ',', requires = "right_on")]
+    pub left_on: Option<Vec<String>>,
+    /// 右表的键列
+    #[arg(long, value_delimiter = ',', requires = "left_on")]
+    pub right_on: Option<Vec<String>>,
+    /// 连接方式：inner、left、right、full
+    #[arg(long, default_value = "inner")]
+    pub how: JoinKind,
+    /// 两边有同名的非键列时，左表列名加的后缀
+    #[arg(long, default_value = "_left")]
+    pub left_suffix: String,
+    /// 两边有同名的非键列时，右表列名加的后缀
+    #[arg(long, default_value = "_right")]
+    pub right_suffix: String,
+    /// 右表不超过这么多行时在内存中做哈希连接，否则两边外部排序后归并连接
+    #[arg(long, default_value_t = 1_000_000)]
+    pub max_memory_rows: usize,
+    #[command(flatten)]
+    pub read: CsvReadArgs,
+    #[command(flatten)]
+    pub write: CsvWriteArgs,
+}
+
+#[derive(Parser, Debug)]
+pub struct DiffOpts {
+    #[arg(value_parser = verify_file_exists)]
+    pub old: String,
+    #[arg(value_parser = verify_file_exists)]
+    pub new: String,
+    /// 用于对应两个文件中同一行的键列，例如 --key id
+    #[arg(long, value_delimiter = ',', required = true)]
+    pub key: Vec<String>,
+    /// 输出形式：table、json，或 patch（带 _change 列的 CSV）
+    #[arg(long, default_value = "table")]
+    pub format: DiffFormat,
+    /// 输出位置，`-` 表示标准输出
+    #[arg(short, long, default_value = "-")]
     pub output: String,
+    #[command(flatten)]
+    pub read: CsvReadArgs,
+}
 
-    #[arg(long,default_value="json",value_parser=verify_format_valid)]
-    pub format: String,
+#[derive(Parser, Debug)]
+pub struct ValidateOpts {
+    #[arg(value_parser = verify_file_exists)]
+    pub input: String,
+    /// schema 文件：.json 按 JSON Schema 解析，其余按 TOML（[[columns]] 列表）解析
+    #[arg(long, value_parser = verify_file_exists)]
+    pub schema: String,
+    /// 把不合法的行（附加 _errors 列）写入这个 CSV 文件并继续检查；
+    /// 不指定时遇到第一行不合法的数据就报错退出
+    #[arg(long)]
+    pub rejects: Option<String>,
+    #[command(flatten)]
+    pub read: CsvReadArgs,
+}
+
+#[derive(Parser, Debug)]
+pub struct SchemaOpts {
+    #[arg(value_parser = verify_file_exists)]
+    pub input: String,
+    /// 输出形式：toml（可直接用于 csv validate）、json-schema，或 rust（带 serde 属性的结构体）
+    #[arg(long, default_value = "toml")]
+    pub emit: SchemaEmit,
+    /// 生成 Rust 结构体时使用的类型名，默认由文件名转换而来
+    #[arg(long)]
+    pub struct_name: Option<String>,
+    /// 只读取前 N 行推断类型，默认读取整个文件
+    #[arg(long)]
+    pub sample_rows: Option<usize>,
+    /// 输出位置，`-` 表示标准输出
+    #[arg(short, long, default_value = "-")]
+    pub output: String,
+    #[command(flatten)]
+    pub read: CsvReadArgs,
+}
 
-    #[arg(short, long, default_value = ",")]
-    pub delimiter: String,
-    #[arg(long,action=SetFalse,default_value_t=true)]
+#[derive(Parser, Debug)]
+#[command(group(ArgGroup::new("mode").required(true).args(["rows", "by"])))]
+pub struct SplitOpts {
+    #[arg(value_parser = verify_file_exists)]
+    pub input: String,
+    /// 每个文件最多包含的行数
+    #[arg(long)]
+    pub rows: Option<usize>,
+    /// 按这一列的值拆分，每个值一个文件
+    #[arg(long)]
+    pub by: Option<String>,
+    /// 输出目录，不存在时自动创建
+    #[arg(long, default_value = ".")]
+    pub output_dir: String,
+    /// 输出文件名前缀，默认使用输入文件名（去掉扩展名）
+    #[arg(long)]
+    pub prefix: Option<String>,
+    #[command(flatten)]
+    pub read: CsvReadArgs,
+}
+
+#[derive(Parser, Debug)]
+pub struct CatOpts {
+    #[arg(required = true, value_parser = verify_file_exists)]
+    pub inputs: Vec<String>,
+    /// 所有文件的列必须相同（顺序可以不同），否则报错；默认取所有列的并集，缺少的列留空
+    #[arg(long)]
+    pub strict: bool,
+    /// 输出位置，`-` 表示标准输出
+    #[arg(short, long, default_value = "-")]
+    pub output: String,
+    #[command(flatten)]
+    pub read: CsvReadArgs,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SchemaEmit {
+    JsonSchema,
+    Toml,
+    Rust,
+}
+
+impl FromStr for SchemaEmit {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "json-schema" | "json" => Ok(SchemaEmit::JsonSchema),
+            "toml" => Ok(SchemaEmit::Toml),
+            "rust" | "rs" => Ok(SchemaEmit::Rust),
+            _ => Err(anyhow::anyhow!("Invalid schema format: {}", s)),
+        }
+    }
+}
+
+/// `--profile` 可选的内置记录类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecordProfile {
+    Person,
+}
+
+impl FromStr for RecordProfile {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "person" => Ok(RecordProfile::Person),
+            _ => Err(anyhow::anyhow!("Invalid record profile: {}", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiffFormat {
+    Table,
+    Json,
+    Patch,
+}
+
+impl FromStr for DiffFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "table" => Ok(DiffFormat::Table),
+            "json" => Ok(DiffFormat::Json),
+            "patch" | "csv" => Ok(DiffFormat::Patch),
+            _ => Err(anyhow::anyhow!("Invalid diff format: {}", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JoinKind {
+    Inner,
+    Left,
+    Right,
+    Full,
+}
+
+impl FromStr for JoinKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "inner" => Ok(JoinKind::Inner),
+            "left" => Ok(JoinKind::Left),
+            "right" => Ok(JoinKind::Right),
+            "full" | "outer" => Ok(JoinKind::Full),
+            _ => Err(anyhow::anyhow!("Invalid join kind: {}", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AggFunc {
+    Count,
+    Sum,
+    Avg,
+    Min,
+    Max,
+    Median,
+    Distinct,
+    First,
+    Last,
+}
+
+impl FromStr for AggFunc {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "count" => Ok(AggFunc::Count),
+            "sum" => Ok(AggFunc::Sum),
+            "avg" | "mean" => Ok(AggFunc::Avg),
+            "min" => Ok(AggFunc::Min),
+            "max" => Ok(AggFunc::Max),
+            "median" => Ok(AggFunc::Median),
+            "distinct" | "count_distinct" => Ok(AggFunc::Distinct),
+            "first" => Ok(AggFunc::First),
+            "last" => Ok(AggFunc::Last),
+            _ => Err(anyhow::anyhow!("Invalid aggregate function: {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for AggFunc {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            AggFunc::Count => "count",
+            AggFunc::Sum => "sum",
+            AggFunc::Avg => "avg",
+            AggFunc::Min => "min",
+            AggFunc::Max => "max",
+            AggFunc::Median => "median",
+            AggFunc::Distinct => "distinct",
+            AggFunc::First => "first",
+            AggFunc::Last => "last",
+        };
+        f.write_str(name)
+    }
+}
+
+/// 一个聚合表达式，例如 `count`、`avg(id)`；`column` 为 None 表示按行计数
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Aggregation {
+    pub func: AggFunc,
+    pub column: Option<String>,
+}
+
+impl FromStr for Aggregation {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let (func, column) = match s.split_once('(') {
+            Some((func, rest)) => {
+                let column = rest
+                    .strip_suffix(')')
+                    .ok_or_else(|| anyhow::anyhow!("expected func(column), got: {}", s))?
+                    .trim();
+                (func.trim(), (column != "*").then(|| column.to_string()))
+            }
+            None => (s, None),
+        };
+        let func: AggFunc = func.parse()?;
+        if column.is_none() && func != AggFunc::Count {
+            return Err(anyhow::anyhow!(
+                "{} needs a column, e.g. {}(id)",
+                func,
+                func
+            ));
+        }
+        Ok(Aggregation { func, column })
+    }
+}
+
+impl fmt::Display for Aggregation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.column {
+            Some(column) => write!(f, "{}({})", self.func, column),
+            None => write!(f, "{}", self.func),
+        }
+    }
+}
+
+#[derive(Parser, Debug)]
+pub struct StatsOpts {
+    #[arg(value_parser = verify_file_exists)]
+    pub input: String,
+    /// 统计结果的输出位置，`-` 表示标准输出
+    #[arg(short, long, default_value = "-")]
+    pub output: String,
+    /// 输出形式：table（对齐的文本表格）或 json
+    #[arg(long, default_value = "table")]
+    pub format: StatsFormat,
+    #[command(flatten)]
+    pub read: CsvReadArgs,
+    /// 每列列出的高频值个数
+    #[arg(long, default_value_t = 5)]
+    pub top: usize,
+    /// 精确统计不同值个数的上限，超出后改用 HyperLogLog 估算
+    #[arg(long, default_value_t = 100_000)]
+    pub distinct_limit: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StatsFormat {
+    Table,
+    Json,
+}
+
+impl FromStr for StatsFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "table" => Ok(StatsFormat::Table),
+            "json" => Ok(StatsFormat::Json),
+            _ => Err(anyhow::anyhow!("Invalid stats format: {}", s)),
+        }
+    }
+}
+
+/// 控制如何解析 CSV 输入，各个 csv 相关命令共用
+#[derive(Args, Debug, Clone)]
+pub struct CsvReadArgs {
+    /// 字段分隔符，支持 `\t` 或 `tab` 表示制表符
+    #[arg(short, long, default_value = ",", value_parser = parse_csv_byte)]
+    pub delimiter: u8,
+    /// 输入没有表头行，列名按 col1..colN 生成（或使用 --columns）
+    // 旧版本的 --header 开关同样表示没有表头，作为隐藏别名保留
+    #[arg(long = "no-header", alias = "header", action = SetFalse)]
     pub header: bool,
-    #[arg(short, long, default_value_t = true)]
-    pub verbose: bool,
+    /// 指定列名，覆盖表头或自动生成的列名，例如 --columns a,b,c
+    #[arg(long, value_delimiter = ',')]
+    pub columns: Option<Vec<String>>,
+    #[arg(long, default_value = "\"", value_parser = parse_csv_byte)]
+    pub quote: u8,
+    /// 转义字符，未指定时使用连续两个引号表示转义
+    #[arg(long, value_parser = parse_csv_byte)]
+    pub escape: Option<u8>,
+    /// 以该字符开头的行视为注释并跳过
+    #[arg(long, value_parser = parse_csv_byte)]
+    pub comment: Option<u8>,
+    /// 输入编码：auto、utf-8、gbk、utf-16le、latin1；auto 按 BOM 和内容自动识别。
+    /// 带 BOM 的输入总是按 BOM 解码，BOM 本身会被去掉
+    #[arg(long, default_value = "auto")]
+    pub encoding: TextEncoding,
+}
 
-    #[arg(long, default_value = "items")]
-    pub toml_root_key: String,
+impl Default for CsvReadArgs {
+    fn default() -> Self {
+        Self {
+            delimiter: b',',
+            header: true,
+            columns: None,
+            quote: b'"',
+            escape: None,
+            comment: None,
+            encoding: TextEncoding::Auto,
+        }
+    }
+}
+
+fn parse_csv_byte(s: &str) -> Result<u8, anyhow::Error> {
+    match s {
+        "\\t" | "tab" => Ok(b'\t'),
+        _ if s.len() == 1 && s.is_ascii() => Ok(s.as_bytes()[0]),
+        _ => Err(anyhow::anyhow!(
+            "expected a single ASCII character, got: {:?}",
+            s
+        )),
+    }
+}
+
+/// CSV 列的值类型，`Null` 表示该列全部为空
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColumnType {
+    String,
+    Int,
+    Float,
+    Bool,
+    Date,
+    Datetime,
+    Null,
+}
+
+impl FromStr for ColumnType {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "string" | "str" => Ok(ColumnType::String),
+            "int" | "integer" => Ok(ColumnType::Int),
+            "float" | "number" => Ok(ColumnType::Float),
+            "bool" | "boolean" => Ok(ColumnType::Bool),
+            "date" => Ok(ColumnType::Date),
+            "datetime" => Ok(ColumnType::Datetime),
+            "null" => Ok(ColumnType::Null),
+            _ => Err(anyhow::anyhow!("Invalid column type: {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for ColumnType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ColumnType::String => "string",
+            ColumnType::Int => "int",
+            ColumnType::Float => "float",
+            ColumnType::Bool => "bool",
+            ColumnType::Date => "date",
+            ColumnType::Datetime => "datetime",
+            ColumnType::Null => "null",
+        };
+        f.write_str(name)
+    }
+}
+
+impl Serialize for ColumnType {
+    fn serialize<S: serde::Serializer>(
+        &self,
+        serializer: S,
+    ) -> std::result::Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
 }
 
+impl<'de> Deserialize<'de> for ColumnType {
+    fn deserialize<D: serde::Deserializer<'de>>(
+        deserializer: D,
+    ) -> std::result::Result<Self, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        name.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+fn parse_rename(s: &str) -> Result<(String, String), anyhow::Error> {
+    let (from, to) = s
+        .split_once('=')
+        .ok_or_else(|| anyhow::anyhow!("expected old=new, got: {}", s))?;
+    Ok((from.trim().to_string(), to.trim().to_string()))
+}
+
+fn parse_type_override(s: &str) -> Result<(String, ColumnType), anyhow::Error> {
+    let (column, column_type) = s
+        .split_once('=')
+        .ok_or_else(|| anyhow::anyhow!("expected column=type, got: {}", s))?;
+    Ok((column.trim().to_string(), column_type.trim().parse()?))
+}
+
+fn parse_mask(s: &str) -> Result<(String, MaskRule), anyhow::Error> {
+    let (column, rule) = s
+        .split_once('=')
+        .ok_or_else(|| anyhow::anyhow!("expected column=rule, got: {}", s))?;
+    Ok((column.trim().to_string(), rule.trim().parse()?))
+}
+
+/// 单列的脱敏规则，空值始终保持为空
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MaskRule {
+    /// 带密钥的 BLAKE3 哈希，保留前 N 个十六进制字符（默认 16）
+    Hash(usize),
+    /// IP 地址只保留前 N 位网络前缀（默认 IPv4 /24、IPv6 /48）；指定了 N 的规则遇到非 IP 的值
+    /// 替换为 ***，未指定 N 时非 IP 的值只保留第一个字符
+    Truncate(Option<u32>),
+    /// 替换成固定的 ***
+    Redact,
+    /// 按原值确定性地替换成同类假数据，未指定种类时按列名猜测
+    Fake(Option<FieldKind>),
+}
+
+impl FromStr for MaskRule {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, arg) = match s.split_once('/') {
+            Some((name, arg)) => (name, Some(arg)),
+            None => (s, None),
+        };
+        let invalid = || anyhow::anyhow!("Invalid mask rule: {}", s);
+        match (name.to_lowercase().as_str(), arg) {
+            ("hash", None) => Ok(MaskRule::Hash(16)),
+            ("hash", Some(len)) => match len.parse::<usize>() {
+                Ok(len @ 1..=64) => Ok(MaskRule::Hash(len)),
+                _ => Err(invalid()),
+            },
+            ("truncate", None) => Ok(MaskRule::Truncate(None)),
+            ("truncate", Some(bits)) => Ok(MaskRule::Truncate(Some(
+                bits.parse().map_err(|_| invalid())?,
+            ))),
+            ("redact", None) => Ok(MaskRule::Redact),
+            ("fake", None) => Ok(MaskRule::Fake(None)),
+            ("fake", Some(kind)) => match kind.parse()? {
+                FieldKind::Id | FieldKind::Choice => Err(invalid()),
+                kind => Ok(MaskRule::Fake(Some(kind))),
+            },
+            _ => Err(invalid()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum OutputFormat {
     Yaml,
     Json,
     Toml,
+    Csv,
+    Jsonl,
+    Markdown,
+    Html,
+    Xml,
+    YamlStream,
+}
+
+/// 输出格式注册表中的一项：可用名称（第一个为规范名称）和对应的文件扩展名
+pub struct FormatSpec {
+    pub format: OutputFormat,
+    pub names: &'static [&'static str],
+    pub extensions: &'static [&'static str],
+}
+
+/// 输出格式注册表。`FromStr`、`verify_format_valid` 和按扩展名推断格式都只查这张表，
+/// 新增格式只需要在这里登记。多个格式共用扩展名时，按扩展名推断取排在前面的一个。
+pub const OUTPUT_FORMATS: &[FormatSpec] = &[
+    FormatSpec {
+        format: OutputFormat::Json,
+        names: &["json"],
+        extensions: &["json"],
+    },
+    FormatSpec {
+        format: OutputFormat::Jsonl,
+        names: &["jsonl", "ndjson"],
+        extensions: &["jsonl", "ndjson"],
+    },
+    FormatSpec {
+        format: OutputFormat::Yaml,
+        names: &["yaml", "yml"],
+        extensions: &["yaml", "yml"],
+    },
+    FormatSpec {
+        format: OutputFormat::YamlStream,
+        names: &["yaml-stream", "yamls"],
+        extensions: &["yaml", "yml"],
+    },
+    FormatSpec {
+        format: OutputFormat::Toml,
+        names: &["toml"],
+        extensions: &["toml"],
+    },
+    FormatSpec {
+        format: OutputFormat::Csv,
+        names: &["csv"],
+        extensions: &["csv"],
+    },
+    FormatSpec {
+        format: OutputFormat::Markdown,
+        names: &["markdown", "md"],
+        extensions: &["md", "markdown"],
+    },
+    FormatSpec {
+        format: OutputFormat::Html,
+        names: &["html"],
+        extensions: &["html", "htm"],
+    },
+    FormatSpec {
+        format: OutputFormat::Xml,
+        names: &["xml"],
+        extensions: &["xml"],
+    },
+];
+
+impl OutputFormat {
+    fn spec(&self) -> &'static FormatSpec {
+        OUTPUT_FORMATS
+            .iter()
+            .find(|spec| spec.format == *self)
+            .expect("every output format is registered")
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.spec().names[0]
+    }
+
+    /// 该格式的默认文件扩展名
+    pub fn extension(&self) -> &'static str {
+        self.spec().extensions[0]
+    }
+
+    /// 该格式能否表达数字、布尔等原生类型；CSV、表格和 XML 只有文本
+    pub fn is_typed(&self) -> bool {
+        !matches!(
+            self,
+            OutputFormat::Csv | OutputFormat::Markdown | OutputFormat::Html | OutputFormat::Xml
+        )
+    }
+
+    pub fn supported_names() -> Vec<&'static str> {
+        OUTPUT_FORMATS.iter().map(|spec| spec.names[0]).collect()
+    }
+
+    /// 按文件扩展名推断格式，扩展名无法识别时返回 None
+    pub fn from_path(path: &str) -> Option<Self> {
+        let ext = path_extension(path)?;
+        OUTPUT_FORMATS
+            .iter()
+            .find(|spec| spec.extensions.contains(&ext.as_str()))
+            .map(|spec| spec.format)
+    }
+
+    /// 该格式是否可以写入带这个扩展名的文件；无法识别的扩展名不做限制
+    pub fn matches_path(&self, path: &str) -> bool {
+        match path_extension(path) {
+            Some(ext) if OutputFormat::from_path(path).is_some() => {
+                self.spec().extensions.contains(&ext.as_str())
+            }
+            _ => true,
+        }
+    }
+}
+
+/// 文件格式的扩展名，忽略压缩后缀，例如 out.json.gz 得到 json
+fn path_extension(path: &str) -> Option<String> {
+    Path::new(Compression::strip_suffix(path))
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(str::to_lowercase)
 }
 
 impl FromStr for OutputFormat {
     type Err = anyhow::Error;
 
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let name = s.to_lowercase();
+        OUTPUT_FORMATS
+            .iter()
+            .find(|spec| spec.names.contains(&name.as_str()))
+            .map(|spec| spec.format)
+            .ok_or_else(|| {
+                anyhow::anyhow!(
+                    "Invalid output format: {}, supported formats: {}",
+                    s,
+                    OutputFormat::supported_names().join(", ")
+                )
+            })
+    }
+}
+
+impl fmt::Display for OutputFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputFormat {
+    Csv,
+    Json,
+    Yaml,
+    Toml,
+    /// Excel（xlsx/xlsm/xlsb/xls）或 OpenDocument（ods）表格
+    Sheet,
+}
+
+impl InputFormat {
+    /// 按扩展名判断输入格式，无法识别的扩展名按 CSV 处理
+    pub fn from_path(path: &str) -> Self {
+        let ext = path_extension(path).unwrap_or_default();
+        match ext.as_str() {
+            "json" => InputFormat::Json,
+            "yaml" | "yml" => InputFormat::Yaml,
+            "toml" => InputFormat::Toml,
+            "xlsx" | "xlsm" | "xlsb" | "xls" | "ods" => InputFormat::Sheet,
+            _ => InputFormat::Csv,
+        }
+    }
+}
+
+impl FromStr for InputFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "csv" => Ok(InputFormat::Csv),
+            "json" => Ok(InputFormat::Json),
+            "yaml" | "yml" => Ok(InputFormat::Yaml),
+            "toml" => Ok(InputFormat::Toml),
+            "xlsx" | "xlsm" | "xlsb" | "xls" | "ods" | "sheet" => Ok(InputFormat::Sheet),
+            _ => Err(anyhow::anyhow!("Invalid input format: {}", s)),
+        }
+    }
+}
+
+/// 输入输出文件的压缩方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    Gzip,
+    Zstd,
+}
+
+impl Compression {
+    /// 按 .gz/.zst 后缀判断压缩方式
+    pub fn from_path(path: &str) -> Option<Self> {
+        let ext = Path::new(path).extension()?.to_str()?;
+        if ext.eq_ignore_ascii_case("gz") {
+            Some(Compression::Gzip)
+        } else if ext.eq_ignore_ascii_case("zst") {
+            Some(Compression::Zstd)
+        } else {
+            None
+        }
+    }
+
+    /// 去掉路径末尾的压缩后缀
+    pub fn strip_suffix(path: &str) -> &str {
+        match Compression::from_path(path) {
+            Some(Compression::Gzip) => &path[..path.len() - ".gz".len()],
+            Some(Compression::Zstd) => &path[..path.len() - ".zst".len()],
+            None => path,
+        }
+    }
+}
+
+impl FromStr for Compression {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "gzip" | "gz" => Ok(Compression::Gzip),
+            "zstd" | "zst" => Ok(Compression::Zstd),
+            _ => Err(anyhow::anyhow!("Invalid compression: {}", s)),
+        }
+    }
+}
+
+/// 表格中的单元格区域，行列都从 0 开始；`end` 为 None 时读到数据末尾
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CellRange {
+    pub start: (u32, u32),
+    pub end: Option<(u32, u32)>,
+}
+
+impl FromStr for CellRange {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || anyhow::anyhow!("Invalid cell range: {}, expected A1:F100 or A1", s);
+        let (start, end) = match s.split_once(':') {
+            Some((start, end)) => (start, Some(end)),
+            None => (s, None),
+        };
+        let start = parse_cell(start).ok_or_else(invalid)?;
+        let end = match end {
+            Some(end) => Some(parse_cell(end).ok_or_else(invalid)?),
+            None => None,
+        };
+        if let Some(end) = end
+            && (end.0 < start.0 || end.1 < start.1)
+        {
+            return Err(invalid());
+        }
+        Ok(CellRange { start, end })
+    }
+}
+
+/// 解析 A1 形式的单元格地址，返回从 0 开始的 (行, 列)
+fn parse_cell(cell: &str) -> Option<(u32, u32)> {
+    let cell = cell.trim().to_ascii_uppercase();
+    let split = cell.find(|c: char| c.is_ascii_digit())?;
+    let (letters, digits) = cell.split_at(split);
+    if letters.is_empty() || !letters.chars().all(|c| c.is_ascii_uppercase()) {
+        return None;
+    }
+    let mut col: u32 = 0;
+    for c in letters.chars() {
+        col = col
+            .checked_mul(26)?
+            .checked_add(c as u32 - 'A' as u32 + 1)?;
+    }
+    let row: u32 = digits.parse().ok()?;
+    if row == 0 {
+        return None;
+    }
+    Some((row - 1, col - 1))
+}
+
+/// 文本编码，gbk 按 GB18030 解码，latin1 按 windows-1252 处理
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextEncoding {
+    Auto,
+    Utf8,
+    Gbk,
+    Utf16le,
+    Latin1,
+}
+
+impl FromStr for TextEncoding {
+    type Err = anyhow::Error;
+
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         match s.to_lowercase().as_str() {
-            "yaml" => Ok(OutputFormat::Yaml),
-            "json" => Ok(OutputFormat::Json),
-            "toml" => Ok(OutputFormat::Toml),
-            _ => Err(anyhow::anyhow!("Invalid output format: {}", s)),
+            "auto" => Ok(TextEncoding::Auto),
+            "utf-8" | "utf8" => Ok(TextEncoding::Utf8),
+            "gbk" | "gb18030" | "gb2312" => Ok(TextEncoding::Gbk),
+            "utf-16le" | "utf16le" | "utf-16" => Ok(TextEncoding::Utf16le),
+            "latin1" | "iso-8859-1" | "windows-1252" => Ok(TextEncoding::Latin1),
+            _ => Err(anyhow::anyhow!("Invalid encoding: {}", s)),
         }
     }
 }
-#[derive(Debug, Serialize, Deserialize)]
+/// 内置的 `--profile` 记录类型，对应 assets/MOCK_DATA.csv 的列；
+/// 下游 crate 可以仿照它定义自己的结构体并调用 `convert_typed::<T>`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "PascalCase")]
 pub struct Person {
     #[serde(rename = "id")]
-    id: i32,
+    pub id: i32,
     #[serde(rename = "first_name")]
-    first_name: String,
+    pub first_name: String,
     #[serde(rename = "last_name")]
-    last_name: String,
+    pub last_name: String,
     #[serde(rename = "email")]
-    email: String,
+    pub email: String,
     #[serde(rename = "gender")]
-    gender: String,
+    pub gender: String,
     #[serde(rename = "ip_address")]
-    ip_address: String,
+    pub ip_address: String,
     #[serde(rename = "city")]
-    city: String,
+    pub city: String,
     #[serde(rename = "avatar")]
-    avatar: String,
+    pub avatar: String,
     #[serde(rename = "car")]
-    car: String,
+    pub car: String,
 }
 
 #[derive(Serialize)]
 pub struct TomlRoot {
     #[serde(flatten)]
-    pub data: HashMap<String, Vec<HashMap<String, String>>>,
+    pub data: HashMap<String, Vec<IndexMap<String, String>>>,
 }
@@ -0,0 +1,57 @@
+use clap::Parser;
+use serde::{Deserialize, de::IntoDeserializer};
+use std::str::FromStr;
+
+use crate::cli::csv::CsvWriteArgs;
+use crate::utils::verify_file_exists;
+
+#[derive(Parser, Debug)]
+pub struct FakeOpts {
+    /// 字段定义文件（TOML），每个 [[fields]] 指定列名 name 和数据种类 kind
+    #[arg(long, value_parser = verify_file_exists)]
+    pub schema: String,
+    /// 生成的行数
+    #[arg(long, default_value_t = 100)]
+    pub rows: usize,
+    /// 随机数种子，指定后每次生成的数据完全相同
+    #[arg(long)]
+    pub seed: Option<u64>,
+    #[command(flatten)]
+    pub write: CsvWriteArgs,
+}
+
+/// 假数据的种类，schema 文件和 --mask col=fake/<kind> 中使用 snake_case 名称
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FieldKind {
+    Id,
+    Uuid,
+    FirstName,
+    LastName,
+    FullName,
+    Email,
+    Gender,
+    Ipv4,
+    Ipv6,
+    City,
+    Url,
+    Avatar,
+    Car,
+    Date,
+    Datetime,
+    Int,
+    Float,
+    Bool,
+    Choice,
+}
+
+impl FromStr for FieldKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let deserializer: serde::de::value::StrDeserializer<serde::de::value::Error> =
+            s.into_deserializer();
+        FieldKind::deserialize(deserializer)
+            .map_err(|_| anyhow::anyhow!("Invalid fake field kind: {}", s))
+    }
+}
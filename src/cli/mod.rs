@@ -1,11 +1,13 @@
 pub mod base64;
 pub mod csv;
+pub mod fake;
 pub mod password;
 pub mod text;
 
 use base64::Base64Ops;
 use clap::Parser;
 use csv::CsvOpts;
+use fake::FakeOpts;
 use password::PasswordOpts;
 use std::path::{Path, PathBuf};
 use text::TextSubcommand;
@@ -29,7 +31,9 @@ pub struct Opts {
 #[derive(Parser, Debug)]
 pub enum SubCommand {
     #[command(name = "csv", about = "Convert CSV file to other format")]
-    Csv(CsvOpts),
+    Csv(Box<CsvOpts>),
+    #[command(name = "fake", about = "Generate fake records from a field schema")]
+    Fake(FakeOpts),
     #[command(name = "password-gen", about = "Generate password")]
     Password(PasswordOpts),
     #[command(subcommand)]
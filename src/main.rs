@@ -4,10 +4,20 @@ use base64::Engine;
 use base64::prelude::BASE64_URL_SAFE_NO_PAD;
 use clap::Parser;
 use first_cli::cli::base64::Base64Ops;
-use first_cli::cli::csv::OutputFormat; // 导入 OutputFormat
+use first_cli::cli::csv::CsvSubcommand;
 use first_cli::cli::{self, SubCommand};
 use first_cli::process::b64::{handle_decode, handle_encode};
-use first_cli::process::csv_generate::{process_toml, read_csv_data};
+use first_cli::process::csv_aggregate::{process_aggregate, process_pivot};
+use first_cli::process::csv_cat::process_cat;
+use first_cli::process::csv_diff::process_diff;
+use first_cli::process::csv_generate::process_csv;
+use first_cli::process::csv_join::process_join;
+use first_cli::process::csv_query::process_query;
+use first_cli::process::csv_schema::process_schema;
+use first_cli::process::csv_split::process_split;
+use first_cli::process::csv_stats::process_stats;
+use first_cli::process::csv_validate::process_validate;
+use first_cli::process::fake_generate::process_fake;
 use first_cli::process::password_generate::password_gen;
 // 导入 cli 模块和 SubCommand 枚举
 use first_cli::process::text::{process_key_generate, process_text_sign, process_text_verify};
@@ -18,20 +28,21 @@ fn main() -> Result<(), anyhow::Error> {
     let args = Opts::parse();
     let cmd = args.cmd;
     match cmd {
-        SubCommand::Csv(cmd) => {
-            // parser the csv input
-            let file = fs::File::open(cmd.input)?;
-            let mut csv_reader = csv::Reader::from_reader(file);
-            let csv_data = read_csv_data(&mut csv_reader)?;
-            // write to json file
-            let format = cmd.format.parse::<OutputFormat>()?;
-            let content = match format {
-                OutputFormat::Json => serde_json::to_string_pretty(&csv_data)?,
-                OutputFormat::Yaml => serde_yaml::to_string(&csv_data)?,
-                OutputFormat::Toml => process_toml(&csv_data, cmd.toml_root_key)?,
-            };
-            fs::write(cmd.output, content)?;
-        }
+        SubCommand::Csv(cmd) => match &cmd.cmd {
+            Some(CsvSubcommand::Stats(opts)) => process_stats(opts)?,
+            Some(CsvSubcommand::Aggregate(opts)) => process_aggregate(opts)?,
+            Some(CsvSubcommand::Pivot(opts)) => process_pivot(opts)?,
+            Some(CsvSubcommand::Query(opts)) => process_query(opts)?,
+            Some(CsvSubcommand::Join(opts)) => process_join(opts)?,
+            Some(CsvSubcommand::Diff(opts)) => process_diff(opts)?,
+            Some(CsvSubcommand::Validate(opts)) => process_validate(opts)?,
+            Some(CsvSubcommand::Schema(opts)) => process_schema(opts)?,
+            Some(CsvSubcommand::Split(opts)) => process_split(opts)?,
+            Some(CsvSubcommand::Cat(opts)) => process_cat(opts)?,
+            // 流式转换：csv::Reader 读出的每一行直接写入输出文件
+            None => process_csv(&cmd)?,
+        },
+        SubCommand::Fake(opts) => process_fake(&opts)?,
         SubCommand::Password(cmd) => {
             let password = password_gen(
                 cmd.length,
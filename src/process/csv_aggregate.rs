@@ -0,0 +1,368 @@
+use anyhow::{Result, anyhow, bail};
+use indexmap::{IndexMap, IndexSet};
+use std::collections::{HashMap, HashSet};
+
+use crate::cli::csv::{AggFunc, AggregateOpts, Aggregation, PivotOpts};
+use crate::process::csv_generate::{
+    RecordStream, duplicate_column, find_column, open_csv_input, write_stream,
+};
+use crate::process::csv_sort::{SortKey, compare_rows, compare_values};
+
+pub fn process_aggregate(opts: &AggregateOpts) -> Result<()> {
+    let stream = open_csv_input(&opts.input, &opts.read, opts.r#where.as_deref())?;
+    let stream = aggregate_stream(stream, &opts.group_by, &opts.agg)?;
+    write_stream(stream, &opts.write)?;
+    Ok(())
+}
+
+pub fn process_pivot(opts: &PivotOpts) -> Result<()> {
+    let stream = open_csv_input(&opts.input, &opts.read, opts.r#where.as_deref())?;
+    let stream = pivot_stream(
+        stream,
+        &opts.rows,
+        &opts.cols,
+        opts.value.as_deref(),
+        opts.agg,
+        &opts.fill,
+    )?;
+    write_stream(stream, &opts.write)?;
+    Ok(())
+}
+
+/// 单个聚合函数的中间状态；输入的空值一律跳过（`count` 不带列名时除外）
+#[derive(Debug, Clone)]
+enum Accumulator {
+    Count(usize),
+    Sum(f64),
+    Avg { sum: f64, count: usize },
+    Min(Option<String>),
+    Max(Option<String>),
+    Median(Vec<f64>),
+    Distinct(HashSet<String>),
+    First(Option<String>),
+    Last(Option<String>),
+}
+
+impl Accumulator {
+    fn new(func: AggFunc) -> Self {
+        match func {
+            AggFunc::Count => Accumulator::Count(0),
+            AggFunc::Sum => Accumulator::Sum(0.0),
+            AggFunc::Avg => Accumulator::Avg { sum: 0.0, count: 0 },
+            AggFunc::Min => Accumulator::Min(None),
+            AggFunc::Max => Accumulator::Max(None),
+            AggFunc::Median => Accumulator::Median(Vec::new()),
+            AggFunc::Distinct => Accumulator::Distinct(HashSet::new()),
+            AggFunc::First => Accumulator::First(None),
+            AggFunc::Last => Accumulator::Last(None),
+        }
+    }
+
+    /// `value` 为 None 表示按行计数（`count` / `count(*)`）
+    fn update(&mut self, value: Option<&str>, column: &str, record_number: usize) -> Result<()> {
+        let raw = match value {
+            Some("") => return Ok(()),
+            Some(raw) => raw,
+            None => {
+                if let Accumulator::Count(count) = self {
+                    *count += 1;
+                }
+                return Ok(());
+            }
+        };
+        let number = || {
+            raw.trim().parse::<f64>().map_err(|_| {
+                anyhow!(
+                    "record:{} column:{} cannot parse {:?} as number",
+                    record_number,
+                    column,
+                    raw
+                )
+            })
+        };
+        match self {
+            Accumulator::Count(count) => *count += 1,
+            Accumulator::Sum(sum) => *sum += number()?,
+            Accumulator::Avg { sum, count } => {
+                *sum += number()?;
+                *count += 1;
+            }
+            Accumulator::Min(min) => {
+                if min
+                    .as_deref()
+                    .is_none_or(|m| compare_values(raw, m).is_lt())
+                {
+                    *min = Some(raw.to_string());
+                }
+            }
+            Accumulator::Max(max) => {
+                if max
+                    .as_deref()
+                    .is_none_or(|m| compare_values(raw, m).is_gt())
+                {
+                    *max = Some(raw.to_string());
+                }
+            }
+            Accumulator::Median(values) => values.push(number()?),
+            Accumulator::Distinct(values) => {
+                if !values.contains(raw) {
+                    values.insert(raw.to_string());
+                }
+            }
+            Accumulator::First(first) => {
+                if first.is_none() {
+                    *first = Some(raw.to_string());
+                }
+            }
+            Accumulator::Last(last) => *last = Some(raw.to_string()),
+        }
+        Ok(())
+    }
+
+    /// 结果仍以文本表示，写出时和其他数据一样经过类型推断
+    fn finish(self) -> String {
+        match self {
+            Accumulator::Count(count) => count.to_string(),
+            Accumulator::Sum(sum) => sum.to_string(),
+            Accumulator::Avg { count: 0, .. } => String::new(),
+            Accumulator::Avg { sum, count } => (sum / count as f64).to_string(),
+            Accumulator::Median(mut values) => {
+                values.sort_by(f64::total_cmp);
+                let n = values.len();
+                match n {
+                    0 => String::new(),
+                    _ if n % 2 == 1 => values[n / 2].to_string(),
+                    _ => ((values[n / 2 - 1] + values[n / 2]) / 2.0).to_string(),
+                }
+            }
+            Accumulator::Distinct(values) => values.len().to_string(),
+            Accumulator::Min(value)
+            | Accumulator::Max(value)
+            | Accumulator::First(value)
+            | Accumulator::Last(value) => value.unwrap_or_default(),
+        }
+    }
+}
+
+/// 按 `group_by` 分组计算聚合，每组输出一行，组按分组列排序（数字按数值比较）。
+/// 输出列为分组列加上每个聚合表达式，列名即表达式本身，例如 `avg(id)`
+pub fn aggregate_stream(
+    stream: RecordStream,
+    group_by: &[String],
+    aggregations: &[Aggregation],
+) -> Result<RecordStream<'static>> {
+    let RecordStream { headers, records } = stream;
+    let group_indices = group_by
+        .iter()
+        .map(|name| find_column(&headers, name, "--group-by"))
+        .collect::<Result<Vec<_>>>()?;
+    let agg_indices = aggregations
+        .iter()
+        .map(|agg| match &agg.column {
+            Some(name) => find_column(&headers, name, "--agg").map(Some),
+            None => Ok(None),
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let mut output_headers = group_by.to_vec();
+    output_headers.extend(aggregations.iter().map(|agg| agg.to_string()));
+    if let Some(name) = duplicate_column(&output_headers) {
+        bail!("duplicate output column {} in --group-by and --agg", name);
+    }
+    let new_accumulators = || -> Vec<Accumulator> {
+        aggregations
+            .iter()
+            .map(|agg| Accumulator::new(agg.func))
+            .collect()
+    };
+
+    let mut groups: IndexMap<Vec<String>, Vec<Accumulator>> = IndexMap::new();
+    for (i, values) in records.enumerate() {
+        let values = values?;
+        let key: Vec<String> = group_indices.iter().map(|&g| values[g].clone()).collect();
+        let accumulators = groups.entry(key).or_insert_with(new_accumulators);
+        for (accumulator, index) in accumulators.iter_mut().zip(&agg_indices) {
+            let value = index.map(|index| values[index].as_str());
+            let column = index.map_or("", |index| headers[index].as_str());
+            accumulator.update(value, column, i + 1)?;
+        }
+    }
+    // 与 SQL 一致：不分组时即使没有数据也输出一行
+    if group_by.is_empty() && groups.is_empty() {
+        groups.insert(Vec::new(), new_accumulators());
+    }
+
+    let keys: Vec<SortKey> = (0..group_by.len())
+        .map(|column| SortKey {
+            column,
+            descending: false,
+        })
+        .collect();
+    groups.sort_by(|a, _, b, _| compare_rows(a, b, &keys));
+
+    let rows = groups.into_iter().map(|(mut key, accumulators)| {
+        key.extend(accumulators.into_iter().map(Accumulator::finish));
+        Ok(key)
+    });
+    Ok(RecordStream::new(output_headers, rows))
+}
+
+/// 透视表：`rows` 的每组取值一行，`cols` 列的每个不同取值一列，单元格是 `value` 列的聚合结果
+pub fn pivot_stream(
+    stream: RecordStream,
+    rows: &[String],
+    cols: &str,
+    value: Option<&str>,
+    func: AggFunc,
+    fill: &str,
+) -> Result<RecordStream<'static>> {
+    let RecordStream { headers, records } = stream;
+    let row_indices = rows
+        .iter()
+        .map(|name| find_column(&headers, name, "--rows"))
+        .collect::<Result<Vec<_>>>()?;
+    let col_index = find_column(&headers, cols, "--cols")?;
+    let value_index = match value {
+        Some(name) => Some(find_column(&headers, name, "--value")?),
+        None if func == AggFunc::Count => None,
+        None => bail!("--value is required for --agg {}", func),
+    };
+
+    let mut cells: IndexMap<Vec<String>, HashMap<String, Accumulator>> = IndexMap::new();
+    let mut columns: IndexSet<String> = IndexSet::new();
+    for (i, values) in records.enumerate() {
+        let values = values?;
+        let key: Vec<String> = row_indices.iter().map(|&r| values[r].clone()).collect();
+        let column = values[col_index].clone();
+        let accumulator = cells
+            .entry(key)
+            .or_default()
+            .entry(column.clone())
+            .or_insert_with(|| Accumulator::new(func));
+        let cell_value = value_index.map(|index| values[index].as_str());
+        let value_column = value_index.map_or("", |index| headers[index].as_str());
+        accumulator.update(cell_value, value_column, i + 1)?;
+        columns.insert(column);
+    }
+
+    let keys: Vec<SortKey> = (0..rows.len())
+        .map(|column| SortKey {
+            column,
+            descending: false,
+        })
+        .collect();
+    cells.sort_by(|a, _, b, _| compare_rows(a, b, &keys));
+    columns.sort_by(|a, b| compare_values(a, b));
+
+    if let Some(name) = duplicate_column(rows) {
+        bail!("duplicate output column {} in --rows", name);
+    }
+    let mut output_headers = rows.to_vec();
+    for column in &columns {
+        let name = if column.is_empty() { "(empty)" } else { column };
+        if output_headers.iter().any(|h| h == name) {
+            bail!("pivot column {} clashes with an existing column name", name);
+        }
+        output_headers.push(name.to_string());
+    }
+    let fill = fill.to_string();
+    let output = cells.into_iter().map(move |(mut key, mut row)| {
+        for column in &columns {
+            key.push(match row.remove(column) {
+                Some(accumulator) => accumulator.finish(),
+                None => fill.clone(),
+            });
+        }
+        Ok(key)
+    });
+    Ok(RecordStream::new(output_headers, output))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stream() -> RecordStream<'static> {
+        let rows = [
+            ["1", "Female", "Ford", "Paris"],
+            ["2", "Male", "Audi", "Paris"],
+            ["3", "Female", "Ford", "Berlin"],
+            ["10", "Female", "Audi", "Paris"],
+            ["5", "Male", "Audi", ""],
+        ];
+        RecordStream::from_rows(&["id", "gender", "car", "city"], &rows)
+    }
+
+    fn collect(stream: RecordStream) -> Result<Vec<Vec<String>>> {
+        stream.records.collect()
+    }
+
+    #[test]
+    fn test_aggregate_groups() -> Result<()> {
+        let aggs: Vec<Aggregation> = ["count", "avg(id)", "max(id)", "count(city)"]
+            .iter()
+            .map(|s| s.parse())
+            .collect::<Result<_>>()?;
+        let group_by = vec!["gender".to_string(), "car".to_string()];
+        let result = aggregate_stream(stream(), &group_by, &aggs)?;
+        assert_eq!(
+            result.headers,
+            vec![
+                "gender",
+                "car",
+                "count",
+                "avg(id)",
+                "max(id)",
+                "count(city)"
+            ]
+        );
+        assert_eq!(
+            collect(result)?,
+            vec![
+                vec!["Female", "Audi", "1", "10", "10", "1"],
+                vec!["Female", "Ford", "2", "2", "3", "2"],
+                vec!["Male", "Audi", "2", "3.5", "5", "1"],
+            ]
+        );
+
+        let total = aggregate_stream(stream(), &[], &["sum(id)".parse()?])?;
+        assert_eq!(collect(total)?, vec![vec!["21"]]);
+        assert!("avg".parse::<Aggregation>().is_err());
+        assert!(aggregate_stream(stream(), &[], &["sum(car)".parse()?]).is_err());
+
+        let counts = ["count".parse()?, "count".parse()?];
+        let err = aggregate_stream(stream(), &[], &counts).err().unwrap();
+        assert!(err.to_string().contains("duplicate output column count"));
+        let renamed = RecordStream::from_rows(&["count", "id"], &[["1", "2"]]);
+        let group_by = vec!["count".to_string()];
+        assert!(aggregate_stream(renamed, &group_by, &["count".parse()?]).is_err());
+        let group_by = vec!["gender".to_string(), "gender".to_string()];
+        let err = aggregate_stream(stream(), &group_by, &counts[..1])
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("duplicate output column gender"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_pivot_counts() -> Result<()> {
+        let rows = vec!["city".to_string()];
+        let result = pivot_stream(stream(), &rows, "gender", None, AggFunc::Count, "0")?;
+        assert_eq!(result.headers, vec!["city", "Female", "Male"]);
+        assert_eq!(
+            collect(result)?,
+            vec![
+                vec!["", "0", "1"],
+                vec!["Berlin", "1", "0"],
+                vec!["Paris", "2", "1"],
+            ]
+        );
+
+        let result = pivot_stream(stream(), &rows, "car", Some("id"), AggFunc::Sum, "")?;
+        assert_eq!(result.headers, vec!["city", "Audi", "Ford"]);
+        assert_eq!(collect(result)?[2], vec!["Paris", "12", "1"]);
+        assert!(pivot_stream(stream(), &rows, "car", None, AggFunc::Sum, "").is_err());
+        let rows = vec!["city".to_string(), "city".to_string()];
+        assert!(pivot_stream(stream(), &rows, "car", None, AggFunc::Count, "").is_err());
+        Ok(())
+    }
+}
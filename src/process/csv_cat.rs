@@ -0,0 +1,138 @@
+use anyhow::{Context, Result, bail};
+use csv::WriterBuilder;
+use indexmap::IndexSet;
+use std::mem;
+
+use crate::cli::csv::CatOpts;
+use crate::process::csv_generate::{RecordStream, open_csv_input};
+use crate::utils::{get_data_writer, ignore_broken_pipe};
+
+pub fn process_cat(opts: &CatOpts) -> Result<()> {
+    let streams = opts
+        .inputs
+        .iter()
+        .map(|path| open_csv_input(path, &opts.read, None))
+        .collect::<Result<Vec<_>>>()?;
+    let stream = cat_streams(streams, &opts.inputs, opts.strict)?;
+    ignore_broken_pipe(&opts.output, write_csv(stream, opts))
+}
+
+fn write_csv(stream: RecordStream, opts: &CatOpts) -> Result<()> {
+    let mut output = get_data_writer(&opts.output, None)
+        .with_context(|| format!("create output file:{} error", opts.output))?;
+    let mut writer = WriterBuilder::new()
+        .delimiter(opts.read.delimiter)
+        .from_writer(&mut output);
+    if opts.read.header || opts.read.columns.is_some() {
+        writer.write_record(&stream.headers)?;
+    }
+    for values in stream.records {
+        writer.write_record(&values?)?;
+    }
+    writer.flush().context("write csv output error")?;
+    drop(writer);
+    output.finish().context("write csv output error")?;
+    Ok(())
+}
+
+/// 依次拼接多个数据流。输出的列是所有输入列的并集（按首次出现的顺序），
+/// 某个文件缺少的列留空；`strict` 时各文件的列集合必须与第一个文件相同
+pub fn cat_streams<'a>(
+    streams: Vec<RecordStream<'a>>,
+    names: &[String],
+    strict: bool,
+) -> Result<RecordStream<'a>> {
+    let mut headers: IndexSet<String> = IndexSet::new();
+    for stream in &streams {
+        headers.extend(stream.headers.iter().cloned());
+    }
+    if strict && let Some(first) = streams.first() {
+        for (stream, name) in streams.iter().zip(names).skip(1) {
+            let missing: Vec<&str> = first
+                .headers
+                .iter()
+                .filter(|h| !stream.headers.contains(h))
+                .map(String::as_str)
+                .collect();
+            let extra: Vec<&str> = stream
+                .headers
+                .iter()
+                .filter(|h| !first.headers.contains(h))
+                .map(String::as_str)
+                .collect();
+            if !missing.is_empty() || !extra.is_empty() {
+                bail!(
+                    "columns of {} differ from {}: missing [{}], extra [{}]",
+                    name,
+                    names[0],
+                    missing.join(", "),
+                    extra.join(", ")
+                );
+            }
+        }
+    }
+
+    let headers: Vec<String> = headers.into_iter().collect();
+    let columns = headers.clone();
+    let records = streams.into_iter().flat_map(move |stream| {
+        // 输出的每一列在这个文件中的下标
+        let positions: Vec<Option<usize>> = columns
+            .iter()
+            .map(|c| stream.headers.iter().position(|h| h == c))
+            .collect();
+        stream.records.map(move |values| {
+            let mut values = values?;
+            Ok(positions
+                .iter()
+                .map(|p| p.map(|i| mem::take(&mut values[i])).unwrap_or_default())
+                .collect())
+        })
+    });
+    Ok(RecordStream::new(headers, records))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names() -> Vec<String> {
+        vec!["a.csv".to_string(), "b.csv".to_string()]
+    }
+
+    #[test]
+    fn test_cat_union_of_columns() -> Result<()> {
+        let a = RecordStream::from_rows(&["id", "car"], &[&["1", "Ford"]]);
+        let b = RecordStream::from_rows(&["city", "id"], &[&["Rome", "2"], &["Oslo", "3"]]);
+        let result = cat_streams(vec![a, b], &names(), false)?;
+        assert_eq!(result.headers, vec!["id", "car", "city"]);
+        let rows: Vec<Vec<String>> = result.records.collect::<Result<_>>()?;
+        assert_eq!(
+            rows,
+            vec![
+                vec!["1", "Ford", ""],
+                vec!["2", "", "Rome"],
+                vec!["3", "", "Oslo"]
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_cat_strict() -> Result<()> {
+        let a = RecordStream::from_rows(&["id", "car"], &[] as &[[&str; 2]]);
+        let b = RecordStream::from_rows(&["car", "id"], &[&["Kia", "1"]]);
+        let rows: Vec<Vec<String>> = cat_streams(vec![a, b], &names(), true)?
+            .records
+            .collect::<Result<_>>()?;
+        assert_eq!(rows, vec![vec!["1", "Kia"]]);
+
+        let a = RecordStream::from_rows(&["id", "car"], &[] as &[[&str; 2]]);
+        let b = RecordStream::from_rows(&["id", "city"], &[] as &[[&str; 2]]);
+        let err = cat_streams(vec![a, b], &names(), true).err().unwrap();
+        assert_eq!(
+            err.to_string(),
+            "columns of b.csv differ from a.csv: missing [car], extra [city]"
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,366 @@
+use anyhow::{Context, Result, bail};
+use csv::WriterBuilder;
+use indexmap::{IndexMap, IndexSet};
+use serde::Serialize;
+use std::{collections::HashSet, io::Write};
+
+use crate::cli::csv::{DiffFormat, DiffOpts};
+use crate::process::csv_generate::{RecordStream, find_column, open_csv_input};
+use crate::process::csv_writer::{truncate_cell, write_text_table};
+use crate::utils::get_writer;
+
+/// 两个 CSV 文件按键比较的结果，字段顺序与输入文件一致，JSON 输出直接序列化这个结构
+#[derive(Debug, Default, Serialize)]
+pub struct CsvDiff {
+    pub key: Vec<String>,
+    pub added_columns: Vec<String>,
+    pub removed_columns: Vec<String>,
+    pub added: Vec<IndexMap<String, String>>,
+    pub removed: Vec<IndexMap<String, String>>,
+    pub modified: Vec<ModifiedRow>,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct ModifiedRow {
+    pub key: IndexMap<String, String>,
+    pub changes: IndexMap<String, CellChange>,
+    /// 新文件中的整行，补丁输出用它写出完整的新值
+    #[serde(skip)]
+    pub after: IndexMap<String, String>,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct CellChange {
+    pub before: String,
+    pub after: String,
+}
+
+impl CsvDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added_columns.is_empty()
+            && self.removed_columns.is_empty()
+            && self.added.is_empty()
+            && self.removed.is_empty()
+            && self.modified.is_empty()
+    }
+}
+
+pub fn process_diff(opts: &DiffOpts) -> Result<()> {
+    let old = open_csv_input(&opts.old, &opts.read, None)?;
+    let new = open_csv_input(&opts.new, &opts.read, None)?;
+    let diff = diff_streams(old, new, &opts.key)?;
+
+    let mut output = get_writer(&opts.output)
+        .with_context(|| format!("create output file:{} error", opts.output))?;
+    match opts.format {
+        DiffFormat::Table => write_diff_table(&mut output, &diff)?,
+        DiffFormat::Json => {
+            serde_json::to_writer_pretty(&mut output, &diff)?;
+            writeln!(output)?;
+        }
+        DiffFormat::Patch => write_patch(&mut output, &diff)?,
+    }
+    output.flush()?;
+    Ok(())
+}
+
+/// 按 `key` 列比较两个数据流。旧文件整个读入内存，新文件逐行比对；
+/// 只比较两边都有的列，新增/删除的列单独列出。键重复时报错
+pub fn diff_streams(old: RecordStream, new: RecordStream, key: &[String]) -> Result<CsvDiff> {
+    let old_keys = key
+        .iter()
+        .map(|name| find_column(&old.headers, name, "--key"))
+        .collect::<Result<Vec<_>>>()?;
+    let new_keys = key
+        .iter()
+        .map(|name| find_column(&new.headers, name, "--key"))
+        .collect::<Result<Vec<_>>>()?;
+    let mut diff = CsvDiff {
+        key: key.to_vec(),
+        added_columns: new
+            .headers
+            .iter()
+            .filter(|h| !old.headers.contains(h))
+            .cloned()
+            .collect(),
+        removed_columns: old
+            .headers
+            .iter()
+            .filter(|h| !new.headers.contains(h))
+            .cloned()
+            .collect(),
+        ..Default::default()
+    };
+    // 共同列在两边的下标
+    let common: Vec<(usize, usize)> = new
+        .headers
+        .iter()
+        .enumerate()
+        .filter_map(|(n, h)| old.headers.iter().position(|o| o == h).map(|o| (o, n)))
+        .collect();
+
+    let RecordStream {
+        headers: old_headers,
+        records: old_records,
+    } = old;
+    let mut old_rows: IndexMap<Vec<String>, Vec<String>> = IndexMap::new();
+    for values in old_records {
+        let values = values?;
+        let row_key: Vec<String> = old_keys.iter().map(|&i| values[i].clone()).collect();
+        if old_rows.contains_key(&row_key) {
+            bail!("duplicate key {} in the old file", row_key.join(","));
+        }
+        old_rows.insert(row_key, values);
+    }
+
+    let RecordStream {
+        headers: new_headers,
+        records: new_records,
+    } = new;
+    let mut seen: HashSet<Vec<String>> = HashSet::new();
+    let mut matched = vec![false; old_rows.len()];
+    for values in new_records {
+        let values = values?;
+        let row_key: Vec<String> = new_keys.iter().map(|&i| values[i].clone()).collect();
+        if !seen.insert(row_key.clone()) {
+            bail!("duplicate key {} in the new file", row_key.join(","));
+        }
+        let Some((index, _, before)) = old_rows.get_full(&row_key) else {
+            diff.added.push(to_record(&new_headers, values));
+            continue;
+        };
+        matched[index] = true;
+        let changes: IndexMap<String, CellChange> = common
+            .iter()
+            .filter(|&&(o, n)| before[o] != values[n])
+            .map(|&(o, n)| {
+                let change = CellChange {
+                    before: before[o].clone(),
+                    after: values[n].clone(),
+                };
+                (new_headers[n].clone(), change)
+            })
+            .collect();
+        if !changes.is_empty() {
+            let key = key.iter().cloned().zip(row_key).collect();
+            let after = to_record(&new_headers, values);
+            diff.modified.push(ModifiedRow {
+                key,
+                changes,
+                after,
+            });
+        }
+    }
+    diff.removed = old_rows
+        .into_values()
+        .zip(matched)
+        .filter(|(_, matched)| !matched)
+        .map(|(values, _)| to_record(&old_headers, values))
+        .collect();
+    Ok(diff)
+}
+
+fn to_record(headers: &[String], values: Vec<String>) -> IndexMap<String, String> {
+    headers.iter().cloned().zip(values).collect()
+}
+
+fn format_key(diff: &CsvDiff, record: &IndexMap<String, String>) -> String {
+    diff.key
+        .iter()
+        .map(|k| format!("{}={}", k, record.get(k).map_or("", String::as_str)))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+const DIFF_CELL_WIDTH: usize = 40;
+
+/// 每个改动一行：新增/删除的行显示整行内容，修改的行每个变化的单元格一行
+fn write_diff_table(output: &mut dyn Write, diff: &CsvDiff) -> Result<()> {
+    for column in &diff.added_columns {
+        writeln!(output, "added column: {}", column)?;
+    }
+    for column in &diff.removed_columns {
+        writeln!(output, "removed column: {}", column)?;
+    }
+    let summarize = |record: &IndexMap<String, String>| {
+        let text = record
+            .iter()
+            .filter(|(k, _)| !diff.key.contains(k))
+            .map(|(k, v)| format!("{}={}", k, v))
+            .collect::<Vec<_>>()
+            .join(", ");
+        truncate_cell(&text, DIFF_CELL_WIDTH * 2)
+    };
+
+    let mut rows = Vec::new();
+    for record in &diff.added {
+        rows.push(vec![
+            "added".to_string(),
+            format_key(diff, record),
+            String::new(),
+            String::new(),
+            summarize(record),
+        ]);
+    }
+    for record in &diff.removed {
+        rows.push(vec![
+            "removed".to_string(),
+            format_key(diff, record),
+            String::new(),
+            summarize(record),
+            String::new(),
+        ]);
+    }
+    for row in &diff.modified {
+        for (column, change) in &row.changes {
+            rows.push(vec![
+                "modified".to_string(),
+                format_key(diff, &row.key),
+                column.clone(),
+                truncate_cell(&change.before, DIFF_CELL_WIDTH),
+                truncate_cell(&change.after, DIFF_CELL_WIDTH),
+            ]);
+        }
+    }
+    if !rows.is_empty() {
+        write_text_table(
+            output,
+            &["change", "key", "column", "before", "after"],
+            &rows,
+        )?;
+    }
+    writeln!(
+        output,
+        "{} added, {} removed, {} modified",
+        diff.added.len(),
+        diff.removed.len(),
+        diff.modified.len()
+    )?;
+    Ok(())
+}
+
+/// 补丁 CSV：第一列 `_change` 为 added/removed/modified，其余为新文件的列。
+/// added 和 modified 行是完整的新行，removed 行是完整的旧行（只保留新文件中仍存在的列）。
+/// modified 行写出整行，值被改成空字符串时与未改动的列可以区分
+fn write_patch(output: &mut dyn Write, diff: &CsvDiff) -> Result<()> {
+    let mut columns: IndexSet<String> = diff.key.iter().cloned().collect();
+    let modified = diff.modified.iter().map(|row| &row.after);
+    for record in diff.added.iter().chain(&diff.removed).chain(modified) {
+        columns.extend(
+            record
+                .keys()
+                .filter(|k| !diff.removed_columns.contains(k))
+                .cloned(),
+        );
+    }
+
+    let mut writer = WriterBuilder::new().from_writer(output);
+    let mut header = vec!["_change"];
+    header.extend(columns.iter().map(String::as_str));
+    writer.write_record(&header)?;
+    let mut write_row = |change: &str, lookup: &dyn Fn(&str) -> Option<String>| {
+        let mut row = vec![change.to_string()];
+        row.extend(columns.iter().map(|c| lookup(c).unwrap_or_default()));
+        writer.write_record(&row)
+    };
+    for record in &diff.added {
+        write_row("added", &|c| record.get(c).cloned())?;
+    }
+    for record in &diff.removed {
+        write_row("removed", &|c| record.get(c).cloned())?;
+    }
+    for row in &diff.modified {
+        write_row("modified", &|c| row.after.get(c).cloned())?;
+    }
+    writer.flush().context("write patch csv error")?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_diff() -> Result<CsvDiff> {
+        let old = RecordStream::from_rows(
+            &["id", "email", "city"],
+            &[
+                &["1", "a@x.com", "Paris"],
+                &["2", "b@x.com", "Rome"],
+                &["3", "c@x.com", "Oslo"],
+            ],
+        );
+        let new = RecordStream::from_rows(
+            &["id", "city", "email", "car"],
+            &[
+                &["3", "Oslo", "c@x.com", "Kia"],
+                &["1", "Lyon", "a@y.com", "Ford"],
+                &["4", "Bari", "d@x.com", ""],
+            ],
+        );
+        diff_streams(old, new, &["id".to_string()])
+    }
+
+    #[test]
+    fn test_diff_rows_and_columns() -> Result<()> {
+        let diff = sample_diff()?;
+        assert_eq!(diff.added_columns, vec!["car"]);
+        assert!(diff.removed_columns.is_empty());
+        assert_eq!(diff.added.len(), 1);
+        assert_eq!(diff.added[0]["id"], "4");
+        assert_eq!(diff.removed.len(), 1);
+        assert_eq!(diff.removed[0]["email"], "b@x.com");
+        assert_eq!(diff.modified.len(), 1);
+        let modified = &diff.modified[0];
+        assert_eq!(modified.key["id"], "1");
+        assert_eq!(
+            modified.changes.keys().collect::<Vec<_>>(),
+            vec!["city", "email"]
+        );
+        assert_eq!(
+            modified.changes["city"],
+            CellChange {
+                before: "Paris".to_string(),
+                after: "Lyon".to_string()
+            }
+        );
+
+        let same = RecordStream::from_rows(&["id", "v"], &[&["1", "a"]]);
+        let same_again = RecordStream::from_rows(&["id", "v"], &[&["1", "a"]]);
+        assert!(diff_streams(same, same_again, &["id".to_string()])?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_patch_output() -> Result<()> {
+        let mut output = Vec::new();
+        write_patch(&mut output, &sample_diff()?)?;
+        assert_eq!(
+            String::from_utf8(output)?,
+            "_change,id,city,email,car\n\
+             added,4,Bari,d@x.com,\n\
+             removed,2,Rome,b@x.com,\n\
+             modified,1,Lyon,a@y.com,Ford\n"
+        );
+        let duplicated = RecordStream::from_rows(&["id"], &[&["1"], &["1"]]);
+        let other = RecordStream::from_rows(&["id"], &[] as &[[&str; 1]]);
+        assert!(diff_streams(duplicated, other, &["id".to_string()]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_patch_keeps_emptied_cells() -> Result<()> {
+        let old = RecordStream::from_rows(&["id", "email", "city"], &[["1", "a@x.com", "Paris"]]);
+        let new = RecordStream::from_rows(&["id", "email", "city"], &[["1", "", "Paris"]]);
+        let diff = diff_streams(old, new, &["id".to_string()])?;
+        assert_eq!(diff.modified[0].changes["email"].after, "");
+
+        // 清空的 email 输出为空，未改动的 city 照常输出
+        let mut output = Vec::new();
+        write_patch(&mut output, &diff)?;
+        assert_eq!(
+            String::from_utf8(output)?,
+            "_change,id,email,city\nmodified,1,,Paris\n"
+        );
+        Ok(())
+    }
+}
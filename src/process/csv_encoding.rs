@@ -0,0 +1,297 @@
+use anyhow::{Result, bail};
+use chardetng::EncodingDetector;
+use encoding_rs::{Encoding, GBK, UTF_8, UTF_16LE, WINDOWS_1252};
+use encoding_rs_io::DecodeReaderBytesBuilder;
+use std::io::{self, BufRead, BufReader, Read, Write};
+
+use crate::cli::csv::TextEncoding;
+
+/// 自动识别编码时最多采样的字节数
+const SAMPLE_BYTES: usize = 64 * 1024;
+
+/// 把输入转换成 UTF-8 并去掉 BOM。UTF-8 输入原样透传；其他编码边读边转码，
+/// 无法解码的字节替换为 U+FFFD
+pub fn decode_reader<'a, R: Read + 'a>(
+    reader: R,
+    encoding: TextEncoding,
+) -> Result<Box<dyn Read + 'a>> {
+    let mut reader = BufReader::with_capacity(SAMPLE_BYTES, reader);
+    let sample = reader.fill_buf()?;
+    let encoding = match Encoding::for_bom(sample) {
+        Some((encoding, bom_len)) if encoding == UTF_8 => {
+            reader.consume(bom_len);
+            UTF_8
+        }
+        // 其余的 BOM 交给解码器识别并去掉
+        Some((encoding, _)) => encoding,
+        // 采样是合法的 UTF-8 时先按 UTF-8 透传，后面遇到非法字节再重新识别
+        None if encoding == TextEncoding::Auto && detect(sample) == UTF_8 => {
+            return Ok(Box::new(AutoDecodeReader {
+                inner: Some(reader),
+                pending: Vec::new(),
+                checked: 0,
+                decoder: None,
+            }));
+        }
+        None => match encoding {
+            TextEncoding::Auto => detect(sample),
+            encoding => input_encoding(encoding),
+        },
+    };
+    if encoding == UTF_8 {
+        return Ok(Box::new(reader));
+    }
+    Ok(decoder(encoding, reader))
+}
+
+fn decoder<'a, R: Read + 'a>(encoding: &'static Encoding, reader: R) -> Box<dyn Read + 'a> {
+    let decoder = DecodeReaderBytesBuilder::new()
+        .encoding(Some(encoding))
+        .bom_override(true)
+        .build(reader);
+    Box::new(decoder)
+}
+
+/// 自动识别编码时使用：校验并透传 UTF-8，遇到第一个非法的 UTF-8 字节时，
+/// 从该位置起重新采样识别编码，之后的内容按识别结果转码
+struct AutoDecodeReader<'a, R: Read> {
+    inner: Option<R>,
+    /// 已读入但还没交给调用方的字节
+    pending: Vec<u8>,
+    /// `pending` 开头已确认是合法 UTF-8 的字节数
+    checked: usize,
+    decoder: Option<Box<dyn Read + 'a>>,
+}
+
+impl<'a, R: Read + 'a> AutoDecodeReader<'a, R> {
+    /// 读入更多数据，返回是否已到末尾
+    fn fill(&mut self) -> io::Result<bool> {
+        let inner = self.inner.as_mut().expect("not switched to a decoder");
+        let len = self.pending.len();
+        self.pending.resize(len + 8 * 1024, 0);
+        let n = inner.read(&mut self.pending[len..])?;
+        self.pending.truncate(len + n);
+        Ok(n == 0)
+    }
+
+    fn switch(&mut self) -> io::Result<()> {
+        while self.pending.len() < SAMPLE_BYTES && !self.fill()? {}
+        let mut detector = EncodingDetector::new();
+        detector.feed(&self.pending, false);
+        let encoding = detector.guess(None, true);
+        let rest = io::Cursor::new(std::mem::take(&mut self.pending));
+        let inner = self.inner.take().expect("switched only once");
+        self.decoder = Some(decoder(encoding, rest.chain(inner)));
+        Ok(())
+    }
+}
+
+impl<'a, R: Read + 'a> Read for AutoDecodeReader<'a, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            if let Some(decoder) = &mut self.decoder {
+                return decoder.read(buf);
+            }
+            if self.checked == 0 {
+                self.checked = match std::str::from_utf8(&self.pending) {
+                    Ok(text) => text.len(),
+                    Err(e) if e.error_len().is_some() && e.valid_up_to() == 0 => {
+                        self.switch()?;
+                        continue;
+                    }
+                    Err(e) => e.valid_up_to(),
+                };
+            }
+            if self.checked > 0 {
+                let n = self.checked.min(buf.len());
+                buf[..n].copy_from_slice(&self.pending[..n]);
+                self.pending.drain(..n);
+                self.checked -= n;
+                return Ok(n);
+            }
+            // 空的或只剩半个多字节字符，需要更多数据
+            if self.fill()? {
+                // 末尾残缺的字节原样交出，由 CSV 解析报错
+                let n = self.pending.len().min(buf.len());
+                buf[..n].copy_from_slice(&self.pending[..n]);
+                self.pending.drain(..n);
+                return Ok(n);
+            }
+        }
+    }
+}
+
+/// 没有 BOM 时按内容猜测：合法的 UTF-8 优先，否则交给 chardetng
+fn detect(sample: &[u8]) -> &'static Encoding {
+    match std::str::from_utf8(sample) {
+        Ok(_) => return UTF_8,
+        // 采样末尾截断了一个多字节字符
+        Err(e) if e.error_len().is_none() => return UTF_8,
+        Err(_) => {}
+    }
+    let mut detector = EncodingDetector::new();
+    detector.feed(sample, false);
+    detector.guess(None, true)
+}
+
+fn input_encoding(encoding: TextEncoding) -> &'static Encoding {
+    match encoding {
+        TextEncoding::Auto | TextEncoding::Utf8 => UTF_8,
+        TextEncoding::Gbk => GBK,
+        TextEncoding::Utf16le => UTF_16LE,
+        TextEncoding::Latin1 => WINDOWS_1252,
+    }
+}
+
+/// 输出编码在 XML 声明中的名称；UTF-16 输出带 BOM，按规范声明为 UTF-16
+pub fn encoding_label(encoding: Option<TextEncoding>) -> &'static str {
+    match encoding {
+        None | Some(TextEncoding::Auto | TextEncoding::Utf8) => "UTF-8",
+        Some(TextEncoding::Gbk) => "GBK",
+        Some(TextEncoding::Utf16le) => "UTF-16",
+        Some(TextEncoding::Latin1) => "windows-1252",
+    }
+}
+
+/// 按输出编码包装 writer，写入的内容必须是 UTF-8；未指定或 utf-8 时原样返回
+pub fn encode_writer<'a>(
+    writer: Box<dyn Write + 'a>,
+    encoding: Option<TextEncoding>,
+) -> Result<Box<dyn Write + 'a>> {
+    match encoding {
+        None | Some(TextEncoding::Utf8) => Ok(writer),
+        Some(TextEncoding::Auto) => bail!("output encoding cannot be auto"),
+        Some(encoding) => Ok(Box::new(EncodeWriter {
+            inner: writer,
+            encoding,
+            pending: Vec::new(),
+            started: false,
+        })),
+    }
+}
+
+/// 把 UTF-8 字节流转换成目标编码。一次 write 可能在多字节字符中间截断，
+/// 不完整的尾部留到下一次 write 再处理
+struct EncodeWriter<W: Write> {
+    inner: W,
+    encoding: TextEncoding,
+    pending: Vec<u8>,
+    started: bool,
+}
+
+impl<W: Write> EncodeWriter<W> {
+    fn encode(&mut self, text: &str) -> io::Result<()> {
+        match self.encoding {
+            TextEncoding::Utf16le => {
+                if !self.started {
+                    self.inner.write_all(&[0xff, 0xfe])?;
+                }
+                let bytes: Vec<u8> = text.encode_utf16().flat_map(u16::to_le_bytes).collect();
+                self.inner.write_all(&bytes)?;
+            }
+            encoding => {
+                let (bytes, _, had_errors) = input_encoding(encoding).encode(text);
+                if had_errors {
+                    let name = if encoding == TextEncoding::Gbk {
+                        "gbk"
+                    } else {
+                        "latin1"
+                    };
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!(
+                            "output contains characters that cannot be encoded as {}",
+                            name
+                        ),
+                    ));
+                }
+                self.inner.write_all(&bytes)?;
+            }
+        }
+        self.started = true;
+        Ok(())
+    }
+}
+
+impl<W: Write> Write for EncodeWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.pending.extend_from_slice(buf);
+        let valid = match std::str::from_utf8(&self.pending) {
+            Ok(text) => text.len(),
+            Err(e) if e.error_len().is_none() => e.valid_up_to(),
+            Err(e) => return Err(io::Error::new(io::ErrorKind::InvalidData, e)),
+        };
+        let pending = std::mem::take(&mut self.pending);
+        let text = std::str::from_utf8(&pending[..valid]).expect("checked above");
+        self.encode(text)?;
+        self.pending = pending[valid..].to_vec();
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode(bytes: &[u8], encoding: TextEncoding) -> Result<String> {
+        let mut text = String::new();
+        decode_reader(bytes, encoding)?.read_to_string(&mut text)?;
+        Ok(text)
+    }
+
+    #[test]
+    fn test_decode_reader() -> Result<()> {
+        let text = "id,城市\n1,北京\n2,上海\n";
+        let (gbk, _, _) = GBK.encode(text);
+        assert_eq!(decode(&gbk, TextEncoding::Gbk)?, text);
+        assert_eq!(decode(&gbk, TextEncoding::Auto)?, text);
+
+        let mut utf16 = vec![0xff, 0xfe];
+        utf16.extend(text.encode_utf16().flat_map(u16::to_le_bytes));
+        assert_eq!(decode(&utf16, TextEncoding::Auto)?, text);
+
+        let utf8_bom = [b"\xef\xbb\xbf".as_slice(), text.as_bytes()].concat();
+        assert_eq!(decode(&utf8_bom, TextEncoding::Utf8)?, text);
+        assert_eq!(decode(b"caf\xe9", TextEncoding::Latin1)?, "café");
+        Ok(())
+    }
+
+    #[test]
+    fn test_detect_after_sample() -> Result<()> {
+        // 第一个非 ASCII 字节出现在采样范围之后
+        let head = "id,city\n".to_string() + &"1,x\n".repeat(SAMPLE_BYTES / 4 + 1);
+        let text = head.clone() + "2,北京\n3,上海\n4,广州\n";
+        let (gbk, _, _) = GBK.encode(&text);
+        assert_eq!(decode(&gbk, TextEncoding::Auto)?, text);
+
+        // 合法的 UTF-8 不受影响，包括被读取边界截断的多字节字符
+        let text = head + &"城市,".repeat(10_000);
+        assert_eq!(decode(text.as_bytes(), TextEncoding::Auto)?, text);
+        Ok(())
+    }
+
+    #[test]
+    fn test_encode_writer() -> Result<()> {
+        let text = "id,城市\n1,北京\n";
+        for encoding in [TextEncoding::Gbk, TextEncoding::Utf16le] {
+            let mut output = Vec::new();
+            let mut writer = encode_writer(Box::new(&mut output), Some(encoding))?;
+            // 逐字节写入，覆盖多字节字符被截断的情况
+            for byte in text.as_bytes() {
+                writer.write_all(&[*byte])?;
+            }
+            drop(writer);
+            assert_eq!(decode(&output, encoding)?, text);
+        }
+
+        let mut output = Vec::new();
+        let mut writer = encode_writer(Box::new(&mut output), Some(TextEncoding::Latin1))?;
+        assert!(writer.write_all(text.as_bytes()).is_err());
+        assert!(encode_writer(Box::new(io::sink()), Some(TextEncoding::Auto)).is_err());
+        Ok(())
+    }
+}
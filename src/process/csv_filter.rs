@@ -0,0 +1,588 @@
+use std::{borrow::Cow, cmp::Ordering, fmt};
+
+use regex::Regex;
+
+use crate::process::csv_generate::RecordStream;
+
+// --where 表达式：
+//   gender == "Female" && id > 10
+//   email ~ "@icq\.com$"
+//   !is_empty(city) || starts_with(`first name`, "A")
+// 支持 == != < <= > >= ~ !~ && || ! 和括号，and/or/not 可作为关键字使用。
+// 列名可以直接书写，包含空格等特殊字符时用反引号括起来。
+
+/// 表达式语法错误，带出错位置（按字符计）并在显示时用 `^` 标出
+#[derive(Debug)]
+pub struct ParseError {
+    pub position: usize,
+    pub message: String,
+    source: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "syntax error at position {}: {}",
+            self.position, self.message
+        )?;
+        writeln!(f, "  {}", self.source)?;
+        write!(f, "  {}^", " ".repeat(self.position))
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Ident(String),
+    Str(String),
+    Num(f64),
+    Bool(bool),
+    Op(&'static str),
+    LParen,
+    RParen,
+    Comma,
+    End,
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Ident(name) => write!(f, "column `{}`", name),
+            Token::Str(s) => write!(f, "string {:?}", s),
+            Token::Num(n) => write!(f, "number {}", n),
+            Token::Bool(b) => write!(f, "{}", b),
+            Token::Op(op) => write!(f, "'{}'", op),
+            Token::LParen => f.write_str("'('"),
+            Token::RParen => f.write_str("')'"),
+            Token::Comma => f.write_str("','"),
+            Token::End => f.write_str("end of expression"),
+        }
+    }
+}
+
+const OPERATORS: &[&str] = &[
+    "==", "!=", "<=", ">=", "!~", "&&", "||", "<", ">", "~", "!", "=", "-",
+];
+
+fn tokenize(source: &str) -> std::result::Result<Vec<(Token, usize)>, (usize, String)> {
+    let chars: Vec<char> = source.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let start = i;
+        if c.is_whitespace() {
+            i += 1;
+            continue;
+        }
+        let token = match c {
+            '(' => {
+                i += 1;
+                Token::LParen
+            }
+            ')' => {
+                i += 1;
+                Token::RParen
+            }
+            ',' => {
+                i += 1;
+                Token::Comma
+            }
+            '"' | '\'' => {
+                let quote = c;
+                let mut value = String::new();
+                i += 1;
+                loop {
+                    match chars.get(i) {
+                        None => return Err((start, "unterminated string literal".into())),
+                        Some(&ch) if ch == quote => {
+                            i += 1;
+                            break;
+                        }
+                        Some('\\') => {
+                            // \" \' \\ \n \t 为转义，其余反斜杠原样保留，方便书写正则
+                            match chars.get(i + 1) {
+                                Some(&next) if next == quote || next == '\\' => value.push(next),
+                                Some('n') => value.push('\n'),
+                                Some('t') => value.push('\t'),
+                                Some(&next) => {
+                                    value.push('\\');
+                                    value.push(next);
+                                }
+                                None => {
+                                    return Err((start, "unterminated string literal".into()));
+                                }
+                            }
+                            i += 2;
+                        }
+                        Some(&ch) => {
+                            value.push(ch);
+                            i += 1;
+                        }
+                    }
+                }
+                Token::Str(value)
+            }
+            '`' => {
+                let end = chars[i + 1..]
+                    .iter()
+                    .position(|&ch| ch == '`')
+                    .ok_or((start, "unterminated quoted column name".to_string()))?;
+                let name: String = chars[i + 1..i + 1 + end].iter().collect();
+                i += end + 2;
+                Token::Ident(name)
+            }
+            _ if c.is_ascii_digit() => {
+                while i < chars.len()
+                    && (chars[i].is_ascii_digit()
+                        || chars[i] == '.'
+                        || matches!(chars[i], 'e' | 'E')
+                        || (matches!(chars[i], '+' | '-') && matches!(chars[i - 1], 'e' | 'E')))
+                {
+                    i += 1;
+                }
+                let text: String = chars[start..i].iter().collect();
+                let value = text
+                    .parse()
+                    .map_err(|_| (start, format!("invalid number: {}", text)))?;
+                Token::Num(value)
+            }
+            _ if c.is_alphabetic() || c == '_' => {
+                while i < chars.len()
+                    && (chars[i].is_alphanumeric() || matches!(chars[i], '_' | '.'))
+                {
+                    i += 1;
+                }
+                let word: String = chars[start..i].iter().collect();
+                match word.to_lowercase().as_str() {
+                    "and" => Token::Op("&&"),
+                    "or" => Token::Op("||"),
+                    "not" => Token::Op("!"),
+                    "true" => Token::Bool(true),
+                    "false" => Token::Bool(false),
+                    _ => Token::Ident(word),
+                }
+            }
+            _ => {
+                let rest: String = chars[i..chars.len().min(i + 2)].iter().collect();
+                let op = OPERATORS
+                    .iter()
+                    .find(|op| rest.starts_with(*op))
+                    .ok_or((start, format!("unexpected character '{}'", c)))?;
+                i += op.chars().count();
+                Token::Op(op)
+            }
+        };
+        tokens.push((token, start));
+    }
+    tokens.push((Token::End, chars.len()));
+    Ok(tokens)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum CompareOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Function {
+    IsEmpty,
+    Len,
+    Lower,
+    Upper,
+    Trim,
+    Contains,
+    StartsWith,
+    EndsWith,
+}
+
+impl Function {
+    fn lookup(name: &str) -> Option<(Function, usize)> {
+        let found = match name.to_lowercase().as_str() {
+            "is_empty" => (Function::IsEmpty, 1),
+            "len" => (Function::Len, 1),
+            "lower" => (Function::Lower, 1),
+            "upper" => (Function::Upper, 1),
+            "trim" => (Function::Trim, 1),
+            "contains" => (Function::Contains, 2),
+            "starts_with" => (Function::StartsWith, 2),
+            "ends_with" => (Function::EndsWith, 2),
+            _ => return None,
+        };
+        Some(found)
+    }
+}
+
+#[derive(Debug)]
+enum Expr {
+    Column(usize),
+    Str(String),
+    Num(f64),
+    Bool(bool),
+    Not(Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Compare(CompareOp, Box<Expr>, Box<Expr>),
+    Matches(Box<Expr>, Regex, bool),
+    Call(Function, Vec<Expr>),
+}
+
+struct Parser<'h> {
+    tokens: Vec<(Token, usize)>,
+    pos: usize,
+    headers: &'h [String],
+}
+
+type ParseResult<T> = std::result::Result<T, (usize, String)>;
+
+impl Parser<'_> {
+    fn peek(&self) -> &Token {
+        &self.tokens[self.pos].0
+    }
+
+    fn position(&self) -> usize {
+        self.tokens[self.pos].1
+    }
+
+    fn next(&mut self) -> (Token, usize) {
+        let item = self.tokens[self.pos].clone();
+        if item.0 != Token::End {
+            self.pos += 1;
+        }
+        item
+    }
+
+    fn expect(&mut self, expected: Token) -> ParseResult<()> {
+        let (token, at) = self.next();
+        if token == expected {
+            Ok(())
+        } else {
+            Err((at, format!("expected {}, found {}", expected, token)))
+        }
+    }
+
+    fn parse_or(&mut self) -> ParseResult<Expr> {
+        let mut left = self.parse_and()?;
+        while self.peek() == &Token::Op("||") {
+            self.next();
+            left = Expr::Or(Box::new(left), Box::new(self.parse_and()?));
+        }
+        Ok(left)
+    }
+
+    fn parse_and(&mut self) -> ParseResult<Expr> {
+        let mut left = self.parse_not()?;
+        while self.peek() == &Token::Op("&&") {
+            self.next();
+            left = Expr::And(Box::new(left), Box::new(self.parse_not()?));
+        }
+        Ok(left)
+    }
+
+    fn parse_not(&mut self) -> ParseResult<Expr> {
+        if self.peek() == &Token::Op("!") {
+            self.next();
+            return Ok(Expr::Not(Box::new(self.parse_not()?)));
+        }
+        self.parse_compare()
+    }
+
+    fn parse_compare(&mut self) -> ParseResult<Expr> {
+        let left = self.parse_primary()?;
+        let op = match self.peek() {
+            Token::Op("==") | Token::Op("=") => CompareOp::Eq,
+            Token::Op("!=") => CompareOp::Ne,
+            Token::Op("<") => CompareOp::Lt,
+            Token::Op("<=") => CompareOp::Le,
+            Token::Op(">") => CompareOp::Gt,
+            Token::Op(">=") => CompareOp::Ge,
+            Token::Op(op @ ("~" | "!~")) => {
+                let negated = *op == "!~";
+                self.next();
+                let (token, at) = self.next();
+                let Token::Str(pattern) = token else {
+                    return Err((at, format!("expected a regex string, found {}", token)));
+                };
+                let regex =
+                    Regex::new(&pattern).map_err(|e| (at, format!("invalid regex: {}", e)))?;
+                return Ok(Expr::Matches(Box::new(left), regex, negated));
+            }
+            _ => return Ok(left),
+        };
+        self.next();
+        let right = self.parse_primary()?;
+        Ok(Expr::Compare(op, Box::new(left), Box::new(right)))
+    }
+
+    fn parse_primary(&mut self) -> ParseResult<Expr> {
+        let (token, at) = self.next();
+        match token {
+            Token::Str(s) => Ok(Expr::Str(s)),
+            Token::Num(n) => Ok(Expr::Num(n)),
+            Token::Bool(b) => Ok(Expr::Bool(b)),
+            Token::Op("-") => match self.next() {
+                (Token::Num(n), _) => Ok(Expr::Num(-n)),
+                (token, at) => Err((at, format!("expected a number after '-', found {}", token))),
+            },
+            Token::LParen => {
+                let expr = self.parse_or()?;
+                self.expect(Token::RParen)?;
+                Ok(expr)
+            }
+            Token::Ident(name) if self.peek() == &Token::LParen => {
+                let (function, arity) =
+                    Function::lookup(&name).ok_or((at, format!("unknown function: {}", name)))?;
+                self.next();
+                let mut args = Vec::new();
+                if self.peek() != &Token::RParen {
+                    args.push(self.parse_or()?);
+                    while self.peek() == &Token::Comma {
+                        self.next();
+                        args.push(self.parse_or()?);
+                    }
+                }
+                let close = self.position();
+                self.expect(Token::RParen)?;
+                if args.len() != arity {
+                    return Err((
+                        close,
+                        format!("{} expects {} argument(s), got {}", name, arity, args.len()),
+                    ));
+                }
+                Ok(Expr::Call(function, args))
+            }
+            Token::Ident(name) => self
+                .headers
+                .iter()
+                .position(|h| *h == name)
+                .map(Expr::Column)
+                .ok_or_else(|| {
+                    (
+                        at,
+                        format!(
+                            "unknown column: {}, available columns: {}",
+                            name,
+                            self.headers.join(", ")
+                        ),
+                    )
+                }),
+            other => Err((at, format!("unexpected {}", other))),
+        }
+    }
+}
+
+/// 求值的中间结果
+#[derive(Debug, Clone)]
+enum Scalar<'r> {
+    Str(Cow<'r, str>),
+    Num(f64),
+    Bool(bool),
+}
+
+impl Scalar<'_> {
+    fn truthy(&self) -> bool {
+        match self {
+            Scalar::Bool(b) => *b,
+            Scalar::Num(n) => *n != 0.0,
+            Scalar::Str(s) => !s.is_empty(),
+        }
+    }
+
+    fn as_text(&self) -> Cow<'_, str> {
+        match self {
+            Scalar::Str(s) => Cow::Borrowed(s.as_ref()),
+            Scalar::Num(n) => Cow::Owned(n.to_string()),
+            Scalar::Bool(b) => Cow::Owned(b.to_string()),
+        }
+    }
+
+    fn as_number(&self) -> Option<f64> {
+        match self {
+            Scalar::Num(n) => Some(*n),
+            // 只接受有限的数字，inf、NaN 这类文本不当作数字
+            Scalar::Str(s) => s.trim().parse().ok().filter(|n: &f64| n.is_finite()),
+            Scalar::Bool(_) => None,
+        }
+    }
+}
+
+/// 与数字字面量比较时按数值比较，另一边不是数字（如 N/A、空值）时只有 != 成立；
+/// 两边都是文本时，== / != 按文本比较，大小比较在两边都像数字时按数值、否则按字典序
+fn compare(op: CompareOp, left: &Scalar, right: &Scalar) -> bool {
+    let has_number = matches!(left, Scalar::Num(_)) || matches!(right, Scalar::Num(_));
+    let numeric = match (left.as_number(), right.as_number()) {
+        (Some(l), Some(r)) if has_number || !matches!(op, CompareOp::Eq | CompareOp::Ne) => {
+            l.partial_cmp(&r)
+        }
+        _ if has_number => return op == CompareOp::Ne,
+        _ => None,
+    };
+    let ordering = numeric.unwrap_or_else(|| left.as_text().cmp(&right.as_text()));
+    match op {
+        CompareOp::Eq => ordering == Ordering::Equal,
+        CompareOp::Ne => ordering != Ordering::Equal,
+        CompareOp::Lt => ordering == Ordering::Less,
+        CompareOp::Le => ordering != Ordering::Greater,
+        CompareOp::Gt => ordering == Ordering::Greater,
+        CompareOp::Ge => ordering != Ordering::Less,
+    }
+}
+
+impl Expr {
+    fn eval<'r>(&self, values: &'r [String]) -> Scalar<'r> {
+        match self {
+            Expr::Column(i) => Scalar::Str(Cow::Borrowed(values[*i].as_str())),
+            Expr::Str(s) => Scalar::Str(Cow::Owned(s.clone())),
+            Expr::Num(n) => Scalar::Num(*n),
+            Expr::Bool(b) => Scalar::Bool(*b),
+            Expr::Not(inner) => Scalar::Bool(!inner.eval(values).truthy()),
+            Expr::And(l, r) => Scalar::Bool(l.eval(values).truthy() && r.eval(values).truthy()),
+            Expr::Or(l, r) => Scalar::Bool(l.eval(values).truthy() || r.eval(values).truthy()),
+            Expr::Compare(op, l, r) => Scalar::Bool(compare(*op, &l.eval(values), &r.eval(values))),
+            Expr::Matches(inner, regex, negated) => {
+                Scalar::Bool(regex.is_match(&inner.eval(values).as_text()) != *negated)
+            }
+            Expr::Call(function, args) => {
+                let first = args[0].eval(values);
+                let text = first.as_text();
+                match function {
+                    Function::IsEmpty => Scalar::Bool(text.trim().is_empty()),
+                    Function::Len => Scalar::Num(text.chars().count() as f64),
+                    Function::Lower => Scalar::Str(Cow::Owned(text.to_lowercase())),
+                    Function::Upper => Scalar::Str(Cow::Owned(text.to_uppercase())),
+                    Function::Trim => Scalar::Str(Cow::Owned(text.trim().to_string())),
+                    Function::Contains | Function::StartsWith | Function::EndsWith => {
+                        let second = args[1].eval(values);
+                        let needle = second.as_text();
+                        Scalar::Bool(match function {
+                            Function::Contains => text.contains(needle.as_ref()),
+                            Function::StartsWith => text.starts_with(needle.as_ref()),
+                            _ => text.ends_with(needle.as_ref()),
+                        })
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// 解析后的行过滤条件，列名在解析时就解析成下标
+#[derive(Debug)]
+pub struct RowFilter {
+    expr: Expr,
+}
+
+impl RowFilter {
+    pub fn parse(source: &str, headers: &[String]) -> std::result::Result<Self, ParseError> {
+        let to_error = |(position, message): (usize, String)| ParseError {
+            position,
+            message,
+            source: source.to_string(),
+        };
+        let tokens = tokenize(source).map_err(to_error)?;
+        let mut parser = Parser {
+            tokens,
+            pos: 0,
+            headers,
+        };
+        let expr = parser.parse_or().map_err(to_error)?;
+        if parser.peek() != &Token::End {
+            let at = parser.position();
+            return Err(to_error((at, format!("unexpected {}", parser.peek()))));
+        }
+        Ok(Self { expr })
+    }
+
+    pub fn matches(&self, values: &[String]) -> bool {
+        self.expr.eval(values).truthy()
+    }
+}
+
+/// 只保留满足条件的行
+pub fn filter_stream<'a>(stream: RecordStream<'a>, filter: RowFilter) -> RecordStream<'a> {
+    let RecordStream { headers, records } = stream;
+    let records = records.filter(move |values| match values {
+        Ok(values) => filter.matches(values),
+        Err(_) => true,
+    });
+    RecordStream::new(headers, records)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers() -> Vec<String> {
+        ["id", "gender", "email", "city", "first name"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect()
+    }
+
+    fn row(values: &[&str]) -> Vec<String> {
+        values.iter().map(|s| s.to_string()).collect()
+    }
+
+    fn matches(expr: &str, values: &[&str]) -> bool {
+        RowFilter::parse(expr, &headers())
+            .unwrap()
+            .matches(&row(values))
+    }
+
+    #[test]
+    fn test_filter_expressions() {
+        let r = ["11", "Female", "aohm0@icq.com", "", "Amalle"];
+        assert!(matches(r#"gender == "Female" && id > 10"#, &r));
+        assert!(!matches(r#"gender == "Female" && id > 11"#, &r));
+        assert!(matches(r#"email ~ "@icq\.com$""#, &r));
+        assert!(!matches(r#"email !~ "@icq\.com$""#, &r));
+        assert!(matches("is_empty(city)", &r));
+        assert!(matches(
+            r#"not is_empty(city) or `first name` = 'Amalle'"#,
+            &r
+        ));
+        assert!(matches(
+            r#"(id >= 11) && starts_with(lower(gender), "fe")"#,
+            &r
+        ));
+        assert!(matches("len(gender) == 6 && id != -1", &r));
+        // 两边都是文本时 == 按文本比较，大小比较按数值
+        assert!(!matches(r#"id == "011""#, &r));
+        assert!(matches(r#"id > "9""#, &r));
+
+        // 与数字比较时，非数字的单元格不满足任何大小比较
+        for id in ["N/A", "", "inf", "NaN"] {
+            let r = [id, "Female", "", "", ""];
+            assert!(!matches("id > 100", &r), "{}", id);
+            assert!(!matches("id <= 100", &r), "{}", id);
+            assert!(!matches("id == 0", &r), "{}", id);
+            assert!(matches("id != 0", &r), "{}", id);
+        }
+    }
+
+    #[test]
+    fn test_parse_error_position() {
+        let err = RowFilter::parse(r#"gender == "Female" && (id > "#, &headers()).unwrap_err();
+        assert_eq!(err.position, 28);
+        let message = err.to_string();
+        assert!(message.contains("syntax error at position 28"));
+        assert!(message.ends_with(&format!("  {}^", " ".repeat(28))));
+
+        let err = RowFilter::parse("age > 1", &headers()).unwrap_err();
+        assert_eq!(err.position, 0);
+        assert!(err.message.contains("unknown column: age"));
+
+        let err = RowFilter::parse(r#"email ~ "(""#, &headers()).unwrap_err();
+        assert_eq!(err.position, 8);
+
+        assert!(RowFilter::parse("id > 1 city", &headers()).is_err());
+        assert!(RowFilter::parse("contains(city)", &headers()).is_err());
+    }
+}
@@ -1,13 +1,427 @@
-use anyhow::{Context, Ok};
-use csv::Reader;
-use std::{collections::HashMap, io::Read};
+use anyhow::{Context, Ok, bail};
+use csv::{Reader, ReaderBuilder, StringRecord};
+use indexmap::IndexMap;
+use std::{
+    cell::Cell,
+    collections::HashMap,
+    io::{BufReader, BufWriter, Read},
+    time::Instant,
+};
 
-use crate::cli::csv::TomlRoot;
+use crate::cli::csv::{CsvOpts, CsvReadArgs, CsvWriteArgs, InputFormat, OutputFormat, TomlRoot};
+use crate::process::csv_encoding::{decode_reader, encode_writer};
+use crate::process::csv_filter::{RowFilter, filter_stream};
+use crate::process::csv_mask::{Masker, mask_stream};
+use crate::process::csv_nested::Unflattener;
+use crate::process::csv_reverse::open_document_stream;
+use crate::process::csv_select::{select_column_types, select_columns};
+use crate::process::csv_sheet::open_sheet_stream;
+use crate::process::csv_sort::{dedupe_stream, sort_stream};
+use crate::process::csv_typed::{TypedRows, convert_profile};
+use crate::process::csv_types::{infer_stream_types, sort_row_keys};
+use crate::process::csv_writer::{WriterOptions, new_record_writer};
+use crate::process::text::Blake3;
+use crate::utils::{get_content, get_data_reader, get_data_writer, ignore_broken_pipe};
 
 // parse rcli csv --input.csv --output.json() --delimiter=, --header=true --verbose=true
 
+/// 表头加上逐行产出的字段值，CSV 转换的各个处理阶段都基于它串联
+pub struct RecordStream<'a> {
+    pub headers: Vec<String>,
+    pub records: Box<dyn Iterator<Item = Result<Vec<String>, anyhow::Error>> + 'a>,
+}
+
+impl<'a> RecordStream<'a> {
+    pub fn new(
+        headers: Vec<String>,
+        records: impl Iterator<Item = Result<Vec<String>, anyhow::Error>> + 'a,
+    ) -> Self {
+        Self {
+            headers,
+            records: Box::new(records),
+        }
+    }
+}
+
+#[cfg(test)]
+impl RecordStream<'static> {
+    /// 测试用：由字符串字面量构造内存中的数据流
+    pub fn from_rows<'s, R: AsRef<[&'s str]>>(headers: &[&str], rows: &[R]) -> Self {
+        let headers = headers.iter().map(|s| s.to_string()).collect();
+        let rows: Vec<Vec<String>> = rows
+            .iter()
+            .map(|row| row.as_ref().iter().map(|s| s.to_string()).collect())
+            .collect();
+        Self::new(headers, rows.into_iter().map(Ok))
+    }
+}
+
+/// 按列名查找列下标，找不到时报错并列出所有可用的列名
+pub fn find_column(headers: &[String], name: &str, option: &str) -> Result<usize, anyhow::Error> {
+    headers.iter().position(|h| h == name).ok_or_else(|| {
+        anyhow::anyhow!(
+            "unknown column in {}: {}, available columns: {}",
+            option,
+            name,
+            headers.join(", ")
+        )
+    })
+}
+
+/// 第一个重复出现的列名。输出时按列名组装每一行，重名的列会互相覆盖，所以列名必须唯一
+pub fn duplicate_column(headers: &[String]) -> Option<&str> {
+    headers
+        .iter()
+        .enumerate()
+        .find(|(i, name)| headers[..*i].contains(name))
+        .map(|(_, name)| name.as_str())
+}
+
+/// 统计读取/写出行数，verbose 模式下定期在 stderr 汇报进度
+pub struct Progress {
+    enabled: bool,
+    start: Instant,
+    rows_read: Cell<usize>,
+    rows_written: Cell<usize>,
+}
+
+const PROGRESS_INTERVAL: usize = 100_000;
+
+impl Progress {
+    pub fn new(enabled: bool) -> Self {
+        Self {
+            enabled,
+            start: Instant::now(),
+            rows_read: Cell::new(0),
+            rows_written: Cell::new(0),
+        }
+    }
+
+    pub fn rows_read(&self) -> usize {
+        self.rows_read.get()
+    }
+
+    pub fn rows_written(&self) -> usize {
+        self.rows_written.get()
+    }
+
+    pub fn read_row(&self) {
+        let rows_read = self.rows_read.get() + 1;
+        self.rows_read.set(rows_read);
+        if self.enabled && rows_read.is_multiple_of(PROGRESS_INTERVAL) {
+            eprintln!(
+                "read {} rows ({:.2?} elapsed)",
+                rows_read,
+                self.start.elapsed()
+            );
+        }
+    }
+
+    pub fn write_row(&self) {
+        self.rows_written.set(self.rows_written.get() + 1);
+    }
+
+    /// 在数据源处计数，之后被过滤掉的行也算作已读取
+    pub fn track<'s>(&'s self, stream: RecordStream<'s>) -> RecordStream<'s> {
+        let RecordStream { headers, records } = stream;
+        RecordStream::new(headers, records.inspect(move |_| self.read_row()))
+    }
+
+    pub fn finish(&self) {
+        if self.enabled {
+            eprintln!(
+                "rows read: {}, rows written: {}, elapsed: {:.2?}",
+                self.rows_read(),
+                self.rows_written(),
+                self.start.elapsed()
+            );
+        }
+    }
+}
+
+pub fn process_csv(opts: &CsvOpts) -> Result<(), anyhow::Error> {
+    let format = resolve_output_format(opts.format.as_deref(), opts.output.as_deref())?;
+    let output_path = opts.output(format);
+    ignore_broken_pipe(&output_path, convert_csv(opts, format, &output_path))
+}
+
+fn convert_csv(
+    opts: &CsvOpts,
+    format: OutputFormat,
+    output_path: &str,
+) -> Result<(), anyhow::Error> {
+    let input_format = opts
+        .from
+        .unwrap_or_else(|| InputFormat::from_path(opts.input()));
+    if input_format != InputFormat::Sheet && (opts.sheet.is_some() || opts.range.is_some()) {
+        bail!("--sheet and --range only apply to spreadsheet input");
+    }
+    // 表格按单元格类型确定的列类型，作为类型推断的依据
+    let mut sheet_types = Vec::new();
+    let stream = if input_format == InputFormat::Sheet {
+        let (stream, types) =
+            open_sheet_stream(opts.input(), opts.sheet.as_deref(), opts.range, &opts.read)?;
+        sheet_types = types;
+        stream
+    } else {
+        let input = get_data_reader(opts.input())
+            .with_context(|| format!("open input file:{} error", opts.input()))?;
+        match input_format {
+            InputFormat::Csv => open_csv_stream(input, &opts.read)?,
+            _ => open_document_stream(
+                BufReader::new(decode_reader(input, opts.read.encoding)?),
+                input_format,
+                &opts.toml_root_key,
+                &opts.separator,
+            )?,
+        }
+    };
+    let progress = Progress::new(opts.verbose);
+    let mut stream = progress.track(stream);
+    if let Some(condition) = &opts.r#where {
+        let filter = RowFilter::parse(condition, &stream.headers)?;
+        stream = filter_stream(stream, filter);
+    }
+    if !opts.dedupe_by.is_empty() {
+        stream = dedupe_stream(stream, &opts.dedupe_by)?;
+    }
+    if !opts.sort_by.is_empty() {
+        stream = sort_stream(stream, &opts.sort_by, opts.sort_buffer_rows)?;
+    }
+    if !opts.mask.is_empty() {
+        let key = match &opts.mask_key {
+            Some(path) => Some(
+                Blake3::try_new(&get_content(path)?)
+                    .with_context(|| format!("load mask key:{} error", path))?,
+            ),
+            None => None,
+        };
+        let masker = Masker::new(&stream.headers, &opts.mask, key)?;
+        stream = mask_stream(stream, masker);
+    }
+    let stream = select_columns(stream, opts.select.as_deref(), &opts.exclude, &opts.rename)?;
+    let rows: TypedRows = match opts.profile {
+        Some(profile) => convert_profile(profile, stream),
+        None => {
+            // 输出纯文本格式时保留原始文本，只有 --types 指定的列才做类型转换
+            let infer = !opts.no_infer && format.is_typed();
+            let mut types = Vec::new();
+            if infer {
+                // 脱敏后的值不再符合原来的单元格类型
+                sheet_types.retain(|(name, _)| opts.mask.iter().all(|(masked, _)| masked != name));
+                types = select_column_types(
+                    &sheet_types,
+                    opts.select.as_deref(),
+                    &opts.exclude,
+                    &opts.rename,
+                );
+            }
+            types.extend(opts.types.iter().cloned());
+            let (typer, stream) = infer_stream_types(stream, &types, infer, opts.infer_rows)?;
+            let unflattener = if opts.unflatten {
+                Some(Unflattener::new(typer.headers(), &opts.separator)?)
+            } else {
+                None
+            };
+            let rows = stream.records.enumerate().map(move |(i, values)| {
+                let values = values?;
+                match &unflattener {
+                    Some(unflattener) => Ok(unflattener.build(typer.convert(values, i + 1)?)),
+                    None => typer.to_row(values, i + 1),
+                }
+            });
+            Box::new(rows)
+        }
+    };
+
+    let mut output = get_data_writer(output_path, opts.compress)
+        .with_context(|| format!("create output file:{} error", output_path))?;
+    let encoded = encode_writer(Box::new(&mut output), opts.output_encoding)?;
+    let writer_options = WriterOptions {
+        toml_root_key: opts.toml_root_key.clone(),
+        xml_root: opts.xml_root.clone(),
+        xml_row: opts.xml_row.clone(),
+        encoding: opts.output_encoding,
+    };
+    let mut writer = new_record_writer(format, BufWriter::new(encoded), &writer_options);
+
+    // 边读边写，除类型推断的采样行和排序缓冲外，内存中只保留当前这一行
+    for row in rows {
+        let mut row = row?;
+        if opts.sort_keys {
+            sort_row_keys(&mut row);
+        }
+        writer.write_record(&row)?;
+        progress.write_row();
+    }
+    writer.finish()?;
+    drop(writer);
+    output.finish().context("write output error")?;
+    progress.finish();
+    Ok(())
+}
+
+/// 打开 CSV 文件（`-` 表示标准输入），可选地按 --where 表达式过滤
+pub fn open_csv_input<'a>(
+    path: &str,
+    args: &CsvReadArgs,
+    condition: Option<&str>,
+) -> Result<RecordStream<'a>, anyhow::Error> {
+    let input = get_data_reader(path).with_context(|| format!("open input file:{} error", path))?;
+    let stream = open_csv_stream(input, args)?;
+    match condition {
+        Some(condition) => {
+            let filter = RowFilter::parse(condition, &stream.headers)?;
+            Ok(filter_stream(stream, filter))
+        }
+        None => Ok(stream),
+    }
+}
+
+/// 子命令的结果统一从这里写出：推断列类型后交给对应格式的 RecordWriter，返回写出的行数
+pub fn write_stream(stream: RecordStream, args: &CsvWriteArgs) -> Result<usize, anyhow::Error> {
+    ignore_broken_pipe(&args.output, write_records(stream, args))
+}
+
+fn write_records(stream: RecordStream, args: &CsvWriteArgs) -> Result<usize, anyhow::Error> {
+    let format = resolve_output_format(args.format.as_deref(), Some(&args.output))?;
+    let (typer, stream) = infer_stream_types(stream, &[], format.is_typed(), INFER_ROWS)?;
+    let mut output = get_data_writer(&args.output, args.compress)
+        .with_context(|| format!("create output file:{} error", args.output))?;
+    let encoded = encode_writer(Box::new(&mut output), args.output_encoding)?;
+    let writer_options = WriterOptions {
+        toml_root_key: args.toml_root_key.clone(),
+        xml_root: args.xml_root.clone(),
+        xml_row: args.xml_row.clone(),
+        encoding: args.output_encoding,
+    };
+    let mut writer = new_record_writer(format, BufWriter::new(encoded), &writer_options);
+    let mut count = 0;
+    for values in stream.records {
+        count += 1;
+        writer.write_record(&typer.to_row(values?, count)?)?;
+    }
+    writer.finish()?;
+    drop(writer);
+    output.finish().context("write output error")?;
+    Ok(count)
+}
+
+/// 子命令推断列类型时的采样行数，与 csv --infer-rows 的默认值一致
+const INFER_ROWS: usize = 1000;
+
+/// 确定输出格式：显式指定的 --format 优先，但不能与输出文件扩展名矛盾；
+/// 未指定时按扩展名推断，无法推断（包括输出到 stdout）或没有输出文件时默认 json
+pub fn resolve_output_format(
+    format: Option<&str>,
+    output: Option<&str>,
+) -> Result<OutputFormat, anyhow::Error> {
+    match (format, output) {
+        (Some(name), None) => name.parse::<OutputFormat>(),
+        (Some(name), Some(output)) => {
+            let format = name.parse::<OutputFormat>()?;
+            if !format.matches_path(output) {
+                bail!(
+                    "--format {} contradicts the extension of output file {}",
+                    format,
+                    output
+                );
+            }
+            Ok(format)
+        }
+        (None, output) => Ok(output
+            .and_then(OutputFormat::from_path)
+            .unwrap_or(OutputFormat::Json)),
+    }
+}
+
+pub fn build_csv_reader<R: Read>(args: &CsvReadArgs, reader: R) -> Reader<R> {
+    ReaderBuilder::new()
+        .delimiter(args.delimiter)
+        .has_headers(args.header)
+        .quote(args.quote)
+        .escape(args.escape)
+        .double_quote(args.escape.is_none())
+        .comment(args.comment)
+        .from_reader(reader)
+}
+
+/// 按 `args` 解析 CSV，确定表头（文件表头、自动生成的 colN 或 --columns）后返回逐行数据流
+pub fn open_csv_stream<'a, R: Read + 'a>(
+    reader: R,
+    args: &CsvReadArgs,
+) -> Result<RecordStream<'a>, anyhow::Error> {
+    let reader = decode_reader(reader, args.encoding)?;
+    let mut csv_reader = build_csv_reader(args, reader);
+    let mut first_record = None;
+    let mut headers: Vec<String> = if args.header {
+        csv_reader
+            .headers()
+            .context("parse csv header error")?
+            .iter()
+            .map(String::from)
+            .collect()
+    } else {
+        // 没有表头时预读第一行来确定列数，这一行之后仍作为数据输出
+        let mut record = StringRecord::new();
+        if csv_reader
+            .read_record(&mut record)
+            .context("parse csv row:1 error")?
+        {
+            first_record = Some(record);
+        }
+        let len = first_record.as_ref().map_or(0, |r| r.len());
+        (1..=len).map(|i| format!("col{}", i)).collect()
+    };
+
+    if let Some(columns) = &args.columns {
+        if !headers.is_empty() && columns.len() != headers.len() {
+            bail!(
+                "--columns has {} names but the input has {} columns",
+                columns.len(),
+                headers.len()
+            );
+        }
+        headers = columns.clone();
+    }
+    if let Some(name) = duplicate_column(&headers) {
+        bail!(
+            "duplicate column name {} in the input, rename columns with --columns",
+            name
+        );
+    }
+
+    let first_row = if args.header { 2 } else { 1 };
+    let records = first_record
+        .map(std::result::Result::Ok)
+        .into_iter()
+        .chain(csv_reader.into_records());
+    Ok(string_record_stream(headers, records, first_row))
+}
+
+fn string_record_stream<'a>(
+    headers: Vec<String>,
+    records: impl Iterator<Item = csv::Result<StringRecord>> + 'a,
+    first_row: usize,
+) -> RecordStream<'a> {
+    let width = headers.len();
+    let records = records.enumerate().map(move |(row_index, result)| {
+        let record =
+            result.with_context(|| format!("parse csv row:{} error", row_index + first_row))?;
+        let mut values: Vec<String> = record.iter().map(String::from).collect();
+        values.resize(width, String::new());
+        Ok(values)
+    });
+    RecordStream::new(headers, records)
+}
+
+/// 按表头顺序把一行字段组装成有序 map
+pub fn build_row(headers: &[String], values: Vec<String>) -> IndexMap<String, String> {
+    headers.iter().cloned().zip(values).collect()
+}
+
 pub fn process_toml(
-    record_list: &[HashMap<String, String>],
+    record_list: &[IndexMap<String, String>],
     toml_key: String,
 ) -> Result<String, anyhow::Error> {
     let mut root_data = HashMap::new();
@@ -17,27 +431,200 @@ pub fn process_toml(
     Ok(toml::to_string_pretty(&tom_root)?)
 }
 
+/// 逐行解析 CSV 并交给 `handle` 处理，返回处理的行数。
+/// 每行的字段顺序与表头顺序一致。
+pub fn stream_csv_data<R: Read>(
+    reader: &mut Reader<R>,
+    mut handle: impl FnMut(IndexMap<String, String>) -> Result<(), anyhow::Error>,
+) -> Result<usize, anyhow::Error> {
+    let headers: Vec<String> = reader.headers()?.iter().map(String::from).collect();
+    let stream = string_record_stream(headers, reader.records(), 2);
+    let mut row_count = 0;
+    for values in stream.records {
+        handle(build_row(&stream.headers, values?))?;
+        row_count += 1;
+    }
+    Ok(row_count)
+}
+
 pub fn read_csv_data<R: Read>(
     reader: &mut Reader<R>,
-) -> Result<Vec<HashMap<String, String>>, anyhow::Error> {
-    let mut record_list: Vec<HashMap<String, String>> = Vec::new();
+) -> Result<Vec<IndexMap<String, String>>, anyhow::Error> {
+    let mut record_list: Vec<IndexMap<String, String>> = Vec::new();
+    stream_csv_data(reader, |row| {
+        record_list.push(row);
+        Ok(())
+    })?;
+    Ok(record_list)
+}
 
-    let headers = reader.headers()?.clone();
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    for (row_index, result) in reader.records().enumerate() {
-        let record = result.with_context(|| format!("parse csv row:{} error", row_index + 2))?;
+    fn collect(stream: RecordStream) -> Result<Vec<Vec<String>>, anyhow::Error> {
+        stream.records.collect()
+    }
 
-        let mut row = HashMap::new();
+    #[test]
+    fn test_open_csv_stream_with_options() -> Result<(), anyhow::Error> {
+        let args = CsvReadArgs {
+            delimiter: b';',
+            comment: Some(b'#'),
+            ..Default::default()
+        };
+        let data = "id;name\n# skipped\n1;\"a;b\"\n2;c\n";
+        let stream = open_csv_stream(data.as_bytes(), &args)?;
+        assert_eq!(stream.headers, vec!["id", "name"]);
+        assert_eq!(collect(stream)?, vec![vec!["1", "a;b"], vec!["2", "c"]]);
+        Ok(())
+    }
 
-        for (col_index, header) in headers.iter().enumerate() {
-            if let Some(value) = record.get(col_index) {
-                row.insert(header.to_string(), value.to_string());
-            } else {
-                row.insert(header.to_string(), String::new());
-            }
+    #[test]
+    fn test_open_csv_stream_without_header() -> Result<(), anyhow::Error> {
+        let args = CsvReadArgs {
+            delimiter: b'\t',
+            header: false,
+            ..Default::default()
+        };
+        let stream = open_csv_stream("1\ta\n2\tb\n".as_bytes(), &args)?;
+        assert_eq!(stream.headers, vec!["col1", "col2"]);
+        assert_eq!(collect(stream)?.len(), 2);
+
+        let args = CsvReadArgs {
+            columns: Some(vec!["id".into(), "name".into()]),
+            ..args
+        };
+        let stream = open_csv_stream("1\ta\n".as_bytes(), &args)?;
+        assert_eq!(stream.headers, vec!["id", "name"]);
+        assert_eq!(collect(stream)?, vec![vec!["1", "a"]]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_resolve_output_format() -> Result<(), anyhow::Error> {
+        assert_eq!(
+            resolve_output_format(None, Some("out.yml"))?,
+            OutputFormat::Yaml
+        );
+        assert_eq!(
+            resolve_output_format(None, Some("out.txt"))?,
+            OutputFormat::Json
+        );
+        assert_eq!(resolve_output_format(None, Some("-"))?, OutputFormat::Json);
+        assert_eq!(
+            resolve_output_format(Some("toml"), Some("-"))?,
+            OutputFormat::Toml
+        );
+        assert_eq!(
+            resolve_output_format(Some("yaml-stream"), Some("out.yaml"))?,
+            OutputFormat::YamlStream
+        );
+        assert_eq!(
+            resolve_output_format(Some("md"), Some("out.txt"))?,
+            OutputFormat::Markdown
+        );
+        assert!(resolve_output_format(Some("yaml"), Some("out.json")).is_err());
+        assert_eq!(
+            resolve_output_format(None, Some("out.json.gz"))?,
+            OutputFormat::Json
+        );
+        assert_eq!(
+            resolve_output_format(None, Some("out.CSV.zst"))?,
+            OutputFormat::Csv
+        );
+        // 没有 --output 时不检查扩展名，默认文件名随格式变化
+        assert_eq!(
+            resolve_output_format(Some("yaml"), None)?,
+            OutputFormat::Yaml
+        );
+        assert_eq!(resolve_output_format(None, None)?, OutputFormat::Json);
+        assert_eq!(OutputFormat::Yaml.extension(), "yaml");
+        Ok(())
+    }
+
+    #[test]
+    fn test_ignore_broken_pipe() {
+        let broken = || -> Result<usize, anyhow::Error> {
+            let err = std::io::Error::from(std::io::ErrorKind::BrokenPipe);
+            Err(csv::Error::from(err)).context("write csv output error")
+        };
+        assert_eq!(ignore_broken_pipe("-", broken()).unwrap(), 0);
+        // 写文件时的断管仍然报错
+        assert!(ignore_broken_pipe("out.csv", broken()).is_err());
+        let other = anyhow::anyhow!("parse csv row:2 error");
+        assert!(ignore_broken_pipe::<()>("-", Err(other)).is_err());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_compressed_finish_reports_write_error() -> Result<(), anyhow::Error> {
+        use crate::cli::csv::Compression;
+
+        // 没有写入任何数据时，压缩流的头和结尾都在 finish 里写出，写满的设备要在这里报错
+        for compression in [Compression::Gzip, Compression::Zstd] {
+            let output = get_data_writer("/dev/full", Some(compression))?;
+            assert!(output.finish().is_err());
         }
+        Ok(())
+    }
 
-        record_list.push(row);
+    #[test]
+    fn test_compressed_round_trip() -> Result<(), anyhow::Error> {
+        use crate::cli::csv::Compression;
+        use std::io::Write;
+
+        let dir = tempfile::tempdir()?;
+        let data = "id,name\n1,a\n2,b\n";
+        for (name, compression) in [
+            ("data.csv.gz", None),
+            ("data.csv.zst", None),
+            ("data.out", Some(Compression::Zstd)),
+            ("plain.csv", None),
+        ] {
+            let path = dir.path().join(name);
+            let path = path.to_str().unwrap();
+            let mut output = get_data_writer(path, compression)?;
+            output.write_all(data.as_bytes())?;
+            output.finish()?;
+            let raw = std::fs::read(path)?;
+            assert_eq!(raw == data.as_bytes(), name == "plain.csv", "{}", name);
+
+            let stream = open_csv_input(path, &CsvReadArgs::default(), None)?;
+            assert_eq!(stream.headers, vec!["id", "name"]);
+            assert_eq!(collect(stream)?, vec![vec!["1", "a"], vec!["2", "b"]]);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_columns_count_mismatch() {
+        let args = CsvReadArgs {
+            columns: Some(vec!["only_one".into()]),
+            ..Default::default()
+        };
+        assert!(open_csv_stream("a,b\n1,2\n".as_bytes(), &args).is_err());
+    }
+
+    #[test]
+    fn test_duplicate_input_columns() -> Result<(), anyhow::Error> {
+        let data = "id,name,name\n1,a,b\n";
+        let err = open_csv_stream(data.as_bytes(), &CsvReadArgs::default())
+            .err()
+            .unwrap();
+        assert!(
+            err.to_string().contains("duplicate column name name"),
+            "{}",
+            err
+        );
+
+        // 用 --columns 改名后可以读取，两列都保留
+        let args = CsvReadArgs {
+            columns: Some(vec!["id".into(), "first".into(), "second".into()]),
+            ..Default::default()
+        };
+        let stream = open_csv_stream(data.as_bytes(), &args)?;
+        assert_eq!(collect(stream)?, vec![vec!["1", "a", "b"]]);
+        Ok(())
     }
-    Ok(record_list)
 }
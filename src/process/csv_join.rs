@@ -0,0 +1,516 @@
+use anyhow::{Result, bail};
+use std::{
+    cmp::Ordering,
+    collections::{HashMap, VecDeque},
+    iter::Peekable,
+};
+
+use crate::cli::csv::{JoinKind, JoinOpts};
+use crate::process::csv_generate::{RecordStream, find_column, open_csv_input, write_stream};
+use crate::process::csv_sort::{compare_values, sort_stream};
+
+type Records<'a> = Box<dyn Iterator<Item = Result<Vec<String>>> + 'a>;
+
+pub fn process_join(opts: &JoinOpts) -> Result<()> {
+    let (left_on, right_on) = match (&opts.on, &opts.left_on, &opts.right_on) {
+        (Some(on), None, None) => (on.clone(), on.clone()),
+        (None, Some(left_on), Some(right_on)) => (left_on.clone(), right_on.clone()),
+        _ => bail!("use either --on, or both --left-on and --right-on"),
+    };
+    let left = open_csv_input(&opts.left, &opts.read, None)?;
+    let right = open_csv_input(&opts.right, &opts.read, None)?;
+    let spec = JoinSpec {
+        left_on,
+        right_on,
+        kind: opts.how,
+        left_suffix: opts.left_suffix.clone(),
+        right_suffix: opts.right_suffix.clone(),
+        max_memory_rows: opts.max_memory_rows,
+    };
+    write_stream(join_streams(left, right, &spec)?, &opts.write)?;
+    Ok(())
+}
+
+#[derive(Debug, Clone)]
+pub struct JoinSpec {
+    pub left_on: Vec<String>,
+    pub right_on: Vec<String>,
+    pub kind: JoinKind,
+    pub left_suffix: String,
+    pub right_suffix: String,
+    /// 右表超过这么多行时不再做哈希连接，改为两边外部排序后归并连接
+    pub max_memory_rows: usize,
+}
+
+/// 连接两个数据流。右表能放进内存时做哈希连接，输出保持左表的顺序；
+/// 否则两边按键排序后归并连接，输出按键排序。任一键列为空的行不与任何行匹配（同 SQL 的 NULL）
+pub fn join_streams<'a>(
+    left: RecordStream<'a>,
+    right: RecordStream<'a>,
+    spec: &JoinSpec,
+) -> Result<RecordStream<'a>> {
+    if spec.left_on.len() != spec.right_on.len() || spec.left_on.is_empty() {
+        bail!(
+            "--left-on has {} columns but --right-on has {}",
+            spec.left_on.len(),
+            spec.right_on.len()
+        );
+    }
+    let left_keys = spec
+        .left_on
+        .iter()
+        .map(|name| find_column(&left.headers, name, "--left-on"))
+        .collect::<Result<Vec<_>>>()?;
+    let right_keys = spec
+        .right_on
+        .iter()
+        .map(|name| find_column(&right.headers, name, "--right-on"))
+        .collect::<Result<Vec<_>>>()?;
+    let layout = Layout::new(&left.headers, &right.headers, &left_keys, &right_keys, spec)?;
+    let headers = layout.headers.clone();
+
+    let RecordStream {
+        headers: right_headers,
+        records: mut right_records,
+    } = right;
+    let mut right_rows = Vec::new();
+    let mut index: HashMap<Vec<String>, Vec<usize>> = HashMap::new();
+    for values in right_records.by_ref() {
+        let values = values?;
+        if let Some(key) = join_key(&values, &right_keys) {
+            index.entry(key).or_default().push(right_rows.len());
+        }
+        right_rows.push(values);
+        if right_rows.len() > spec.max_memory_rows {
+            // 已读入的行与剩余数据拼回去，两边一起走外部排序
+            let right = RecordStream::new(
+                right_headers,
+                right_rows.into_iter().map(Ok).chain(right_records),
+            );
+            return merge_join(left, right, left_keys, right_keys, layout, spec);
+        }
+    }
+
+    let matched = vec![false; right_rows.len()];
+    let join = HashJoin {
+        left: left.records,
+        right_rows,
+        index,
+        matched,
+        left_keys,
+        layout,
+        kind: spec.kind,
+        pending: VecDeque::new(),
+        next_unmatched: None,
+    };
+    Ok(RecordStream::new(headers, join))
+}
+
+fn join_key(values: &[String], keys: &[usize]) -> Option<Vec<String>> {
+    keys.iter()
+        .map(|&i| Some(values[i].clone()).filter(|v| !v.is_empty()))
+        .collect()
+}
+
+fn compare_keys(a: &[String], b: &[String]) -> Ordering {
+    a.iter()
+        .zip(b)
+        .map(|(a, b)| compare_values(a, b))
+        .find(|ordering| ordering.is_ne())
+        .unwrap_or(Ordering::Equal)
+}
+
+/// 输出列的布局：左表全部列，加上右表中除 `--on` 键以外的列；同名列加后缀区分
+struct Layout {
+    headers: Vec<String>,
+    left_width: usize,
+    right_columns: Vec<usize>,
+    /// `--on` 的键列只输出一次，左表没有匹配时用右表的值补上
+    shared_keys: Vec<(usize, usize)>,
+}
+
+impl Layout {
+    fn new(
+        left: &[String],
+        right: &[String],
+        left_keys: &[usize],
+        right_keys: &[usize],
+        spec: &JoinSpec,
+    ) -> Result<Self> {
+        let shared_keys: Vec<(usize, usize)> = left_keys
+            .iter()
+            .zip(right_keys)
+            .filter(|&(&l, &r)| left[l] == right[r])
+            .map(|(&l, &r)| (l, r))
+            .collect();
+        let right_columns: Vec<usize> = (0..right.len())
+            .filter(|i| !shared_keys.iter().any(|&(_, r)| r == *i))
+            .collect();
+
+        let is_shared = |i: usize| shared_keys.iter().any(|&(l, _)| l == i);
+        let mut headers: Vec<String> = left
+            .iter()
+            .enumerate()
+            .map(|(i, name)| {
+                let conflict = !is_shared(i) && right_columns.iter().any(|&r| right[r] == *name);
+                if conflict {
+                    format!("{}{}", name, spec.left_suffix)
+                } else {
+                    name.clone()
+                }
+            })
+            .collect();
+        for &r in &right_columns {
+            let name = &right[r];
+            if left.contains(name) {
+                headers.push(format!("{}{}", name, spec.right_suffix));
+            } else {
+                headers.push(name.clone());
+            }
+        }
+        for (i, name) in headers.iter().enumerate() {
+            if headers[..i].contains(name) {
+                bail!(
+                    "duplicate output column after joining: {}, try other --left-suffix/--right-suffix",
+                    name
+                );
+            }
+        }
+        Ok(Self {
+            headers,
+            left_width: left.len(),
+            right_columns,
+            shared_keys,
+        })
+    }
+
+    fn combine(&self, left: Option<&[String]>, right: Option<&[String]>) -> Vec<String> {
+        let mut row = match left {
+            Some(left) => left.to_vec(),
+            None => {
+                let mut row = vec![String::new(); self.left_width];
+                if let Some(right) = right {
+                    for &(l, r) in &self.shared_keys {
+                        row[l] = right[r].clone();
+                    }
+                }
+                row
+            }
+        };
+        for &r in &self.right_columns {
+            row.push(right.map(|right| right[r].clone()).unwrap_or_default());
+        }
+        row
+    }
+}
+
+fn keeps_unmatched_left(kind: JoinKind) -> bool {
+    matches!(kind, JoinKind::Left | JoinKind::Full)
+}
+
+fn keeps_unmatched_right(kind: JoinKind) -> bool {
+    matches!(kind, JoinKind::Right | JoinKind::Full)
+}
+
+/// 哈希连接：逐行读取左表并在右表的索引中查找，左表读完后再补出右表中未匹配的行
+struct HashJoin<'a> {
+    left: Records<'a>,
+    right_rows: Vec<Vec<String>>,
+    index: HashMap<Vec<String>, Vec<usize>>,
+    matched: Vec<bool>,
+    left_keys: Vec<usize>,
+    layout: Layout,
+    kind: JoinKind,
+    pending: VecDeque<Vec<String>>,
+    next_unmatched: Option<usize>,
+}
+
+impl Iterator for HashJoin<'_> {
+    type Item = Result<Vec<String>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(row) = self.pending.pop_front() {
+                return Some(Ok(row));
+            }
+            if let Some(position) = self.next_unmatched.as_mut() {
+                while *position < self.right_rows.len() {
+                    let i = *position;
+                    *position += 1;
+                    if !self.matched[i] {
+                        return Some(Ok(self.layout.combine(None, Some(&self.right_rows[i]))));
+                    }
+                }
+                return None;
+            }
+            match self.left.next() {
+                Some(Ok(values)) => {
+                    let matches = join_key(&values, &self.left_keys)
+                        .and_then(|key| self.index.get(&key))
+                        .map_or(&[][..], Vec::as_slice);
+                    for &i in matches {
+                        self.matched[i] = true;
+                        let row = self
+                            .layout
+                            .combine(Some(&values), Some(&self.right_rows[i]));
+                        self.pending.push_back(row);
+                    }
+                    if matches.is_empty() && keeps_unmatched_left(self.kind) {
+                        self.pending
+                            .push_back(self.layout.combine(Some(&values), None));
+                    }
+                }
+                Some(Err(e)) => return Some(Err(e)),
+                None if keeps_unmatched_right(self.kind) => self.next_unmatched = Some(0),
+                None => return None,
+            }
+        }
+    }
+}
+
+fn merge_join<'a>(
+    left: RecordStream<'a>,
+    right: RecordStream<'a>,
+    left_keys: Vec<usize>,
+    right_keys: Vec<usize>,
+    layout: Layout,
+    spec: &JoinSpec,
+) -> Result<RecordStream<'a>> {
+    let headers = layout.headers.clone();
+    let left = sort_stream(left, &spec.left_on, spec.max_memory_rows)?;
+    let right = sort_stream(right, &spec.right_on, spec.max_memory_rows)?;
+    let join = MergeJoin {
+        left: Side::new(left.records, left_keys),
+        right: Side::new(right.records, right_keys),
+        layout,
+        kind: spec.kind,
+        pending: VecDeque::new(),
+    };
+    Ok(RecordStream::new(headers, join))
+}
+
+/// 归并连接中的一侧：已按键排序的数据流，每次取出键相同的一组行
+struct Side<'a> {
+    records: Peekable<Records<'a>>,
+    keys: Vec<usize>,
+}
+
+impl<'a> Side<'a> {
+    fn new(records: Records<'a>, keys: Vec<usize>) -> Self {
+        Self {
+            records: records.peekable(),
+            keys,
+        }
+    }
+
+    fn peek_key(&mut self) -> Result<Option<Option<Vec<String>>>> {
+        match self.records.peek() {
+            Some(Ok(values)) => Ok(Some(join_key(values, &self.keys))),
+            Some(Err(_)) => Err(self.records.next().unwrap().unwrap_err()),
+            None => Ok(None),
+        }
+    }
+
+    /// 取出键与当前第一行相同的所有行；键为空的行单独成组
+    fn next_group(&mut self) -> Result<Vec<Vec<String>>> {
+        let Some(first) = self.records.next().transpose()? else {
+            return Ok(Vec::new());
+        };
+        let Some(key) = join_key(&first, &self.keys) else {
+            return Ok(vec![first]);
+        };
+        let mut group = vec![first];
+        while let Some(Some(next_key)) = self.peek_key()? {
+            if compare_keys(&next_key, &key).is_ne() {
+                break;
+            }
+            group.push(self.records.next().unwrap()?);
+        }
+        Ok(group)
+    }
+}
+
+struct MergeJoin<'a> {
+    left: Side<'a>,
+    right: Side<'a>,
+    layout: Layout,
+    kind: JoinKind,
+    pending: VecDeque<Vec<String>>,
+}
+
+impl MergeJoin<'_> {
+    /// 处理下一组键，把产生的输出行放进 `pending`；两边都读完时返回 false
+    fn advance(&mut self) -> Result<bool> {
+        let left_key = self.left.peek_key()?;
+        let right_key = self.right.peek_key()?;
+        let ordering = match (&left_key, &right_key) {
+            (None, None) => return Ok(false),
+            (Some(_), None) | (Some(None), Some(_)) => Ordering::Less,
+            (None, Some(_)) | (Some(Some(_)), Some(None)) => Ordering::Greater,
+            (Some(Some(l)), Some(Some(r))) => compare_keys(l, r),
+        };
+        match ordering {
+            Ordering::Less => {
+                let group = self.left.next_group()?;
+                if keeps_unmatched_left(self.kind) {
+                    for row in &group {
+                        self.pending.push_back(self.layout.combine(Some(row), None));
+                    }
+                }
+            }
+            Ordering::Greater => {
+                let group = self.right.next_group()?;
+                if keeps_unmatched_right(self.kind) {
+                    for row in &group {
+                        self.pending.push_back(self.layout.combine(None, Some(row)));
+                    }
+                }
+            }
+            Ordering::Equal => {
+                let left = self.left.next_group()?;
+                let right = self.right.next_group()?;
+                for l in &left {
+                    for r in &right {
+                        self.pending
+                            .push_back(self.layout.combine(Some(l), Some(r)));
+                    }
+                }
+            }
+        }
+        Ok(true)
+    }
+}
+
+impl Iterator for MergeJoin<'_> {
+    type Item = Result<Vec<String>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.pending.is_empty() {
+            match self.advance() {
+                Ok(true) => {}
+                Ok(false) => return None,
+                Err(e) => return Some(Err(e)),
+            }
+        }
+        self.pending.pop_front().map(Ok)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn people() -> RecordStream<'static> {
+        RecordStream::from_rows(
+            &["id", "name", "city"],
+            &[
+                &["1", "Ann", "Paris"],
+                &["2", "Bob", "Rome"],
+                &["3", "Cid", ""],
+                &["", "Dee", "Oslo"],
+            ],
+        )
+    }
+
+    fn orders() -> RecordStream<'static> {
+        RecordStream::from_rows(
+            &["id", "car", "city"],
+            &[
+                &["2", "Audi", "Milan"],
+                &["1", "Ford", "Lyon"],
+                &["2", "Kia", "Turin"],
+                &["9", "Fiat", "Bari"],
+            ],
+        )
+    }
+
+    fn spec(kind: JoinKind, max_memory_rows: usize) -> JoinSpec {
+        JoinSpec {
+            left_on: vec!["id".to_string()],
+            right_on: vec!["id".to_string()],
+            kind,
+            left_suffix: "_left".to_string(),
+            right_suffix: "_right".to_string(),
+            max_memory_rows,
+        }
+    }
+
+    fn sorted_rows(stream: RecordStream) -> Result<Vec<String>> {
+        let mut rows: Vec<String> = stream
+            .records
+            .map(|values| Ok(values?.join(",")))
+            .collect::<Result<_>>()?;
+        rows.sort();
+        Ok(rows)
+    }
+
+    #[test]
+    fn test_hash_join_kinds() -> Result<()> {
+        let result = join_streams(people(), orders(), &spec(JoinKind::Inner, 100))?;
+        assert_eq!(
+            result.headers,
+            vec!["id", "name", "city_left", "car", "city_right"]
+        );
+        let rows: Vec<Vec<String>> = result.records.collect::<Result<_>>()?;
+        assert_eq!(
+            rows,
+            vec![
+                vec!["1", "Ann", "Paris", "Ford", "Lyon"],
+                vec!["2", "Bob", "Rome", "Audi", "Milan"],
+                vec!["2", "Bob", "Rome", "Kia", "Turin"],
+            ]
+        );
+
+        let full = join_streams(people(), orders(), &spec(JoinKind::Full, 100))?;
+        assert_eq!(
+            sorted_rows(full)?,
+            vec![
+                ",Dee,Oslo,,",
+                "1,Ann,Paris,Ford,Lyon",
+                "2,Bob,Rome,Audi,Milan",
+                "2,Bob,Rome,Kia,Turin",
+                "3,Cid,,,",
+                "9,,,Fiat,Bari",
+            ]
+        );
+        let right = join_streams(people(), orders(), &spec(JoinKind::Right, 100))?;
+        assert_eq!(sorted_rows(right)?.len(), 4);
+        Ok(())
+    }
+
+    #[test]
+    fn test_merge_join_matches_hash_join() -> Result<()> {
+        for kind in [
+            JoinKind::Inner,
+            JoinKind::Left,
+            JoinKind::Right,
+            JoinKind::Full,
+        ] {
+            let hash = join_streams(people(), orders(), &spec(kind, 100))?;
+            // 右表超过 1 行就改用排序归并
+            let merge = join_streams(people(), orders(), &spec(kind, 1))?;
+            assert_eq!(sorted_rows(hash)?, sorted_rows(merge)?, "{:?}", kind);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_join_on_different_names() -> Result<()> {
+        let cities =
+            RecordStream::from_rows(&["name", "country"], &[&["Paris", "FR"], &["Rome", "IT"]]);
+        let spec = JoinSpec {
+            left_on: vec!["city".to_string()],
+            right_on: vec!["name".to_string()],
+            ..spec(JoinKind::Left, 100)
+        };
+        let result = join_streams(people(), cities, &spec)?;
+        assert_eq!(
+            result.headers,
+            vec!["id", "name_left", "city", "name_right", "country"]
+        );
+        let rows: Vec<Vec<String>> = result.records.collect::<Result<_>>()?;
+        assert_eq!(rows[1], vec!["2", "Bob", "Rome", "Rome", "IT"]);
+        assert_eq!(rows[2], vec!["3", "Cid", "", "", ""]);
+        Ok(())
+    }
+}
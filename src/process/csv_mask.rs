@@ -0,0 +1,250 @@
+use anyhow::{Result, bail};
+use rand::SeedableRng;
+use rand_chacha::ChaCha20Rng;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+use crate::cli::csv::MaskRule;
+use crate::cli::fake::FieldKind;
+use crate::process::csv_generate::{RecordStream, find_column};
+use crate::process::fake_generate::fake_value;
+use crate::process::text::Blake3;
+
+/// 按列脱敏。hash 和 fake 基于带密钥的 BLAKE3：同一个密钥下相同的原值总是得到相同的假名，
+/// 没有密钥则无法由原值推算出假名
+pub struct Masker {
+    columns: Vec<MaskColumn>,
+    key: Option<Blake3>,
+}
+
+struct MaskColumn {
+    index: usize,
+    rule: MaskRule,
+    /// fake 类型是否按列名猜测得出
+    guessed: bool,
+}
+
+impl Masker {
+    pub fn new(
+        headers: &[String],
+        masks: &[(String, MaskRule)],
+        key: Option<Blake3>,
+    ) -> Result<Self> {
+        let mut columns = Vec::with_capacity(masks.len());
+        for (name, rule) in masks {
+            let index = find_column(headers, name, "--mask")?;
+            let guessed = matches!(rule, MaskRule::Fake(None));
+            let rule = match rule {
+                MaskRule::Fake(None) => match guess_fake_kind(name) {
+                    Some(kind) => MaskRule::Fake(Some(kind)),
+                    None => bail!(
+                        "cannot guess the fake data kind for column {}, use {}=fake/<kind>",
+                        name,
+                        name
+                    ),
+                },
+                rule => *rule,
+            };
+            if matches!(rule, MaskRule::Hash(_) | MaskRule::Fake(_)) && key.is_none() {
+                bail!("--mask {}: hash and fake rules require --mask-key", name);
+            }
+            columns.push(MaskColumn {
+                index,
+                rule,
+                guessed,
+            });
+        }
+        Ok(Self { columns, key })
+    }
+
+    pub fn mask(&self, values: &mut [String]) -> Result<()> {
+        for column in &self.columns {
+            let raw = &values[column.index];
+            if raw.is_empty() {
+                continue;
+            }
+            values[column.index] = self.mask_value(column, raw)?;
+        }
+        Ok(())
+    }
+
+    fn mask_value(&self, column: &MaskColumn, raw: &str) -> Result<String> {
+        Ok(match column.rule {
+            MaskRule::Hash(len) => self.hash(raw).to_hex()[..len].to_string(),
+            MaskRule::Truncate(bits) => truncate(raw, bits),
+            MaskRule::Redact => "***".to_string(),
+            MaskRule::Fake(kind) => {
+                let mut kind = kind.unwrap_or(FieldKind::FullName);
+                // 按列名猜测为 IP 时按原值区分 IPv4/IPv6，显式指定的 fake/ipv4 不改
+                if column.guessed && kind == FieldKind::Ipv4 && raw.parse::<Ipv6Addr>().is_ok() {
+                    kind = FieldKind::Ipv6;
+                }
+                // 随机数算法固定，同一个密钥下的假名不随依赖升级而变化
+                let mut rng = ChaCha20Rng::from_seed(*self.hash(raw).as_bytes());
+                fake_value(kind, &mut rng)?
+            }
+        })
+    }
+
+    fn hash(&self, raw: &str) -> blake3::Hash {
+        let key = self.key.as_ref().expect("checked in Masker::new");
+        key.keyed_hash(raw.as_bytes())
+    }
+}
+
+pub fn mask_stream<'a>(stream: RecordStream<'a>, masker: Masker) -> RecordStream<'a> {
+    let RecordStream { headers, records } = stream;
+    let records = records.map(move |values| {
+        let mut values = values?;
+        masker.mask(&mut values)?;
+        Ok(values)
+    });
+    RecordStream::new(headers, records)
+}
+
+/// IP 地址把前缀之后的位清零，前后空白和端口号一并去掉。指定了位数但值不是 IP 时整体替换为 ***，
+/// 以免原值原样漏出；未指定位数时，非 IP 的值只保留第一个字符
+fn truncate(raw: &str, bits: Option<u32>) -> String {
+    let raw = raw.trim();
+    let ip = raw
+        .parse::<IpAddr>()
+        .or_else(|_| raw.parse::<SocketAddr>().map(|addr| addr.ip()));
+    match ip {
+        Ok(IpAddr::V4(ip)) => {
+            let bits = bits.unwrap_or(24).min(32);
+            let mask = u32::MAX.checked_shl(32 - bits).unwrap_or(0);
+            Ipv4Addr::from(u32::from(ip) & mask).to_string()
+        }
+        Ok(IpAddr::V6(ip)) => {
+            let bits = bits.unwrap_or(48).min(128);
+            let mask = u128::MAX.checked_shl(128 - bits).unwrap_or(0);
+            Ipv6Addr::from(u128::from(ip) & mask).to_string()
+        }
+        Err(_) if bits.is_some() => "***".to_string(),
+        Err(_) => raw.chars().take(1).collect(),
+    }
+}
+
+/// 按列名中以 `_`、`-` 或空格分隔的完整单词猜测 fake 类型，如 first_name、ip_address；
+/// 没有认识的单词时返回 None，不做子串匹配以免 description 被当成 IP
+fn guess_fake_kind(column: &str) -> Option<FieldKind> {
+    let column = column.to_lowercase();
+    let tokens: Vec<&str> = column
+        .split(['_', '-', ' '])
+        .filter(|t| !t.is_empty())
+        .collect();
+    // 靠前的优先：first_name 是名而不是全名，avatar_url 是头像而不是网址
+    let kinds: [(&[&str], FieldKind); 11] = [
+        (&["first", "firstname", "given"], FieldKind::FirstName),
+        (&["last", "lastname", "surname"], FieldKind::LastName),
+        (&["email", "mail"], FieldKind::Email),
+        (&["gender", "sex"], FieldKind::Gender),
+        (&["ipv6"], FieldKind::Ipv6),
+        (&["ip", "ipv4"], FieldKind::Ipv4),
+        (&["city"], FieldKind::City),
+        (&["avatar"], FieldKind::Avatar),
+        (&["url", "website"], FieldKind::Url),
+        (&["car"], FieldKind::Car),
+        (&["name", "fullname"], FieldKind::FullName),
+    ];
+    kinds
+        .into_iter()
+        .find(|(words, _)| tokens.iter().any(|t| words.contains(t)))
+        .map(|(_, kind)| kind)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn masker(key: u8) -> Result<Masker> {
+        let headers: Vec<String> = ["email", "ip_address", "last_name", "first_name"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        let masks = [
+            ("email".to_string(), "hash".parse()?),
+            ("ip_address".to_string(), "truncate/24".parse()?),
+            ("last_name".to_string(), "redact".parse()?),
+            ("first_name".to_string(), "fake".parse()?),
+        ];
+        Masker::new(&headers, &masks, Some(Blake3::new([key; 32])))
+    }
+
+    fn row() -> Vec<String> {
+        ["aohm0@icq.com", "67.247.74.198", "Ohm", "Amalle"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_mask_row() -> Result<()> {
+        let mut first = row();
+        masker(1)?.mask(&mut first)?;
+        assert_eq!(first[0].len(), 16);
+        assert_eq!(first[1], "67.247.74.0");
+        assert_eq!(first[2], "***");
+        assert!(!first[3].is_empty() && first[3].chars().all(char::is_alphabetic));
+
+        // 同一个密钥得到相同的假名，换密钥后不同
+        let mut again = row();
+        masker(1)?.mask(&mut again)?;
+        assert_eq!(first, again);
+        let mut other = row();
+        masker(2)?.mask(&mut other)?;
+        assert_ne!(first[0], other[0]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_truncate_and_rules() -> Result<()> {
+        assert_eq!(truncate("2001:db8:abcd:12::1", None), "2001:db8:abcd::");
+        assert_eq!(truncate("10.1.2.3", Some(0)), "0.0.0.0");
+        assert_eq!(truncate("Amalle", None), "A");
+        // 带位数的规则不让非 IP 的值原样漏出
+        assert_eq!(truncate("1.2.3.4 ", Some(24)), "1.2.3.0");
+        assert_eq!(truncate("10.0.0.1:8080", Some(24)), "10.0.0.0");
+        assert_eq!(truncate("[2001:db8::1]:443", Some(32)), "2001:db8::");
+        assert_eq!(truncate("10.0.0.1/8", Some(24)), "***");
+        assert_eq!(truncate("Amalle", Some(2)), "***");
+        assert!("fake/id".parse::<MaskRule>().is_err());
+        assert!("hash/65".parse::<MaskRule>().is_err());
+
+        let headers = vec!["email".to_string(), "notes".to_string()];
+        let hash = [("email".to_string(), MaskRule::Hash(16))];
+        assert!(Masker::new(&headers, &hash, None).is_err());
+        let fake = [("notes".to_string(), MaskRule::Fake(None))];
+        assert!(Masker::new(&headers, &fake, Some(Blake3::new([0; 32]))).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_guess_fake_kind() {
+        assert_eq!(guess_fake_kind("first_name"), Some(FieldKind::FirstName));
+        assert_eq!(guess_fake_kind("Last-Name"), Some(FieldKind::LastName));
+        assert_eq!(guess_fake_kind("ip_address"), Some(FieldKind::Ipv4));
+        assert_eq!(guess_fake_kind("client_ipv6"), Some(FieldKind::Ipv6));
+        assert_eq!(guess_fake_kind("ship_city"), Some(FieldKind::City));
+        assert_eq!(guess_fake_kind("avatar_url"), Some(FieldKind::Avatar));
+        assert_eq!(guess_fake_kind("name"), Some(FieldKind::FullName));
+        for column in ["description", "zip", "card_number", "username", "ipaddr"] {
+            assert_eq!(guess_fake_kind(column), None, "{}", column);
+        }
+    }
+
+    #[test]
+    fn test_fake_ip_kind() -> Result<()> {
+        let headers = vec!["ip".to_string(), "addr".to_string()];
+        let masks = [
+            ("ip".to_string(), "fake".parse()?),
+            ("addr".to_string(), "fake/ipv4".parse()?),
+        ];
+        let masker = Masker::new(&headers, &masks, Some(Blake3::new([0; 32])))?;
+        let mut values = vec!["2001:db8::1".to_string(), "2001:db8::1".to_string()];
+        masker.mask(&mut values)?;
+        // 猜测的 IP 类型跟随原值，显式指定的 ipv4 保持不变
+        assert!(values[0].parse::<Ipv6Addr>().is_ok());
+        assert!(values[1].parse::<Ipv4Addr>().is_ok());
+        Ok(())
+    }
+}
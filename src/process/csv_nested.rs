@@ -0,0 +1,237 @@
+use std::collections::BTreeMap;
+
+use anyhow::{Result, bail};
+use indexmap::IndexMap;
+
+use crate::process::csv_types::Value;
+
+// 根据 `address.city`、`tags[0]` 这类列名还原嵌套结构
+
+#[derive(Debug, Clone, PartialEq)]
+enum Segment {
+    Key(String),
+    Index(usize),
+}
+
+/// 列名解析出的结构模板，叶子节点记录对应的列下标
+#[derive(Debug)]
+enum Node {
+    Leaf(usize),
+    Object(IndexMap<String, Node>),
+    Array(BTreeMap<usize, Node>),
+}
+
+/// 表头确定后一次性构建模板，之后每行按模板组装嵌套值
+#[derive(Debug)]
+pub struct Unflattener {
+    root: IndexMap<String, Node>,
+}
+
+impl Unflattener {
+    pub fn new(headers: &[String], separator: &str) -> Result<Self> {
+        let mut root = IndexMap::new();
+        for (col, header) in headers.iter().enumerate() {
+            let path = parse_path(header, separator);
+            // 数组按最大下标分配空间，下标不能超过列数，以免表头决定分配的内存
+            if let Some(Segment::Index(i)) = path
+                .iter()
+                .find(|s| matches!(s, Segment::Index(i) if *i >= headers.len()))
+            {
+                bail!(
+                    "column {}: array index {} is out of range, at most {} is allowed",
+                    header,
+                    i,
+                    headers.len() - 1
+                );
+            }
+            let Some((Segment::Key(first), rest)) = path.split_first() else {
+                unreachable!("parse_path always starts with a key")
+            };
+            let slot = root.entry(first.clone());
+            insert_path(slot, rest, col, header)?;
+        }
+        Ok(Self { root })
+    }
+
+    pub fn build(&self, values: Vec<Value>) -> IndexMap<String, Value> {
+        let mut values: Vec<Option<Value>> = values.into_iter().map(Some).collect();
+        self.root
+            .iter()
+            .map(|(k, node)| (k.clone(), materialize(node, &mut values)))
+            .collect()
+    }
+}
+
+fn insert_path(
+    slot: indexmap::map::Entry<'_, String, Node>,
+    rest: &[Segment],
+    col: usize,
+    header: &str,
+) -> Result<()> {
+    let node = match (slot, rest.first()) {
+        (indexmap::map::Entry::Vacant(v), None) => {
+            v.insert(Node::Leaf(col));
+            return Ok(());
+        }
+        (indexmap::map::Entry::Vacant(v), Some(Segment::Key(_))) => {
+            v.insert(Node::Object(IndexMap::new()))
+        }
+        (indexmap::map::Entry::Vacant(v), Some(Segment::Index(_))) => {
+            v.insert(Node::Array(BTreeMap::new()))
+        }
+        (indexmap::map::Entry::Occupied(o), _) => o.into_mut(),
+    };
+    insert_child(node, rest, col, header)
+}
+
+fn insert_child(node: &mut Node, rest: &[Segment], col: usize, header: &str) -> Result<()> {
+    let Some((segment, tail)) = rest.split_first() else {
+        bail!("column {} conflicts with another column path", header);
+    };
+    match (node, segment) {
+        (Node::Object(map), Segment::Key(key)) => {
+            insert_path(map.entry(key.clone()), tail, col, header)
+        }
+        (Node::Array(items), Segment::Index(i)) => {
+            let child = match (items.get_mut(i), tail.first()) {
+                (Some(child), _) => child,
+                (None, None) => {
+                    items.insert(*i, Node::Leaf(col));
+                    return Ok(());
+                }
+                (None, Some(Segment::Key(_))) => {
+                    items.entry(*i).or_insert(Node::Object(IndexMap::new()))
+                }
+                (None, Some(Segment::Index(_))) => {
+                    items.entry(*i).or_insert(Node::Array(BTreeMap::new()))
+                }
+            };
+            insert_child(child, tail, col, header)
+        }
+        _ => bail!("column {} conflicts with another column path", header),
+    }
+}
+
+fn materialize(node: &Node, values: &mut [Option<Value>]) -> Value {
+    match node {
+        Node::Leaf(col) => values[*col].take().unwrap_or(Value::Null),
+        Node::Object(map) => Value::Object(
+            map.iter()
+                .map(|(k, child)| (k.clone(), materialize(child, values)))
+                .collect(),
+        ),
+        Node::Array(items) => {
+            // 下标不连续时用 null 补齐
+            let len = items.keys().next_back().map_or(0, |i| i + 1);
+            let mut array = vec![Value::Null; len];
+            for (i, child) in items {
+                array[*i] = materialize(child, values);
+            }
+            Value::Array(array)
+        }
+    }
+}
+
+/// `a.b[0][1]` -> [Key(a), Key(b), Index(0), Index(1)]；无法识别的下标写法按普通键处理
+fn parse_path(header: &str, separator: &str) -> Vec<Segment> {
+    let parts: Vec<&str> = if separator.is_empty() {
+        vec![header]
+    } else {
+        header.split(separator).collect()
+    };
+    if parts.iter().any(|p| p.is_empty()) {
+        return vec![Segment::Key(header.to_string())];
+    }
+
+    let mut path = Vec::new();
+    for part in parts {
+        match split_indices(part) {
+            Some((name, indices)) if !name.is_empty() || !path.is_empty() => {
+                if !name.is_empty() {
+                    path.push(Segment::Key(name.to_string()));
+                }
+                path.extend(indices.into_iter().map(Segment::Index));
+            }
+            _ => path.push(Segment::Key(part.to_string())),
+        }
+    }
+    path
+}
+
+fn split_indices(part: &str) -> Option<(&str, Vec<usize>)> {
+    let start = part.find('[').unwrap_or(part.len());
+    let (name, mut rest) = part.split_at(start);
+    let mut indices = Vec::new();
+    while !rest.is_empty() {
+        let inner = rest.strip_prefix('[')?;
+        let end = inner.find(']')?;
+        indices.push(inner[..end].parse().ok()?);
+        rest = &inner[end + 1..];
+    }
+    Some((name, indices))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers(names: &[&str]) -> Vec<String> {
+        names.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_unflatten_objects_and_arrays() -> Result<()> {
+        let unflattener = Unflattener::new(
+            &headers(&[
+                "id",
+                "address.city",
+                "address.zip",
+                "tags[1]",
+                "tags[0]",
+                "pts[0].x",
+            ]),
+            ".",
+        )?;
+        let row = unflattener.build(vec![
+            Value::Int(1),
+            Value::String("Paris".into()),
+            Value::Null,
+            Value::String("b".into()),
+            Value::String("a".into()),
+            Value::Int(3),
+        ]);
+        let json = serde_json::to_string(&row)?;
+        assert_eq!(
+            json,
+            r#"{"id":1,"address":{"city":"Paris","zip":null},"tags":["a","b"],"pts":[{"x":3}]}"#
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_custom_separator_and_sparse_array() -> Result<()> {
+        let unflattener = Unflattener::new(&headers(&["a__b", "list[2]", "x.y"]), "__")?;
+        let row = unflattener.build(vec![Value::Int(1), Value::Int(2), Value::Int(3)]);
+        assert_eq!(
+            serde_json::to_string(&row)?,
+            r#"{"a":{"b":1},"list":[null,null,2],"x.y":3}"#
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_conflicting_paths() {
+        assert!(Unflattener::new(&headers(&["a", "a.b"]), ".").is_err());
+        assert!(Unflattener::new(&headers(&["a.b", "a"]), ".").is_err());
+        assert!(Unflattener::new(&headers(&["a[0]", "a.b"]), ".").is_err());
+    }
+
+    #[test]
+    fn test_oversized_array_index() {
+        for header in ["tags[18446744073709551615]", "tags[4000000000]", "tags[2]"] {
+            let err = Unflattener::new(&headers(&["id", header]), ".").unwrap_err();
+            assert!(err.to_string().contains("out of range"), "{}", err);
+        }
+        assert!(Unflattener::new(&headers(&["id", "tags[1]"]), ".").is_ok());
+    }
+}
@@ -0,0 +1,196 @@
+use anyhow::{Context, Result, bail};
+use rusqlite::{Connection, params_from_iter, types::Value as SqlValue, types::ValueRef};
+use std::path::Path;
+
+use crate::cli::csv::{ColumnType, QueryOpts};
+use crate::process::csv_generate::{RecordStream, duplicate_column, open_csv_input, write_stream};
+use crate::process::csv_types::{Value, infer_stream_types};
+
+/// 建表时推断列类型的采样行数
+const QUERY_INFER_ROWS: usize = 1000;
+
+/// 把每个输入文件载入内存中的 SQLite 表（表名为文件名去掉扩展名），执行查询后按输出格式写出
+pub fn process_query(opts: &QueryOpts) -> Result<()> {
+    let conn = Connection::open_in_memory().context("open in-memory database error")?;
+    let mut tables: Vec<String> = Vec::new();
+    for path in &opts.inputs {
+        let name = table_name(path);
+        if tables.contains(&name) {
+            bail!("two input files map to the same table name: {}", name);
+        }
+        let stream = open_csv_input(path, &opts.read, None)?;
+        load_table(&conn, &name, stream, !opts.no_infer)
+            .with_context(|| format!("load {} into table {} error", path, name))?;
+        tables.push(name);
+    }
+    let stream = run_query(&conn, &opts.sql)
+        .with_context(|| format!("run query error, available tables: {}", tables.join(", ")))?;
+    write_stream(stream, &opts.write)?;
+    Ok(())
+}
+
+/// 文件名去掉所有扩展名后作为表名，例如 data/input.csv -> input；标准输入的表名为 stdin
+pub fn table_name(path: &str) -> String {
+    if path == "-" {
+        return "stdin".to_string();
+    }
+    let file_name = Path::new(path)
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or(path);
+    // 以点开头的文件名（如 .hidden.csv）没有主名，使用完整文件名
+    match file_name.split_once('.') {
+        Some((stem, _)) if !stem.is_empty() => stem.to_string(),
+        _ => file_name.to_string(),
+    }
+}
+
+/// 按推断的列类型建表并在一个事务内插入全部数据，返回插入的行数
+pub fn load_table(
+    conn: &Connection,
+    name: &str,
+    stream: RecordStream,
+    infer: bool,
+) -> Result<usize> {
+    let (typer, stream) = infer_stream_types(stream, &[], infer, QUERY_INFER_ROWS)?;
+    let columns: Vec<String> = typer
+        .headers()
+        .iter()
+        .zip(typer.types())
+        .map(|(header, column_type)| format!("{} {}", quote_ident(header), sql_type(*column_type)))
+        .collect();
+    conn.execute_batch(&format!(
+        "CREATE TABLE {} ({});",
+        quote_ident(name),
+        columns.join(", ")
+    ))?;
+
+    let placeholders = vec!["?"; typer.headers().len()].join(", ");
+    let insert = format!(
+        "INSERT INTO {} VALUES ({})",
+        quote_ident(name),
+        placeholders
+    );
+    let tx = conn.unchecked_transaction()?;
+    let mut count = 0;
+    {
+        let mut statement = tx.prepare(&insert)?;
+        for values in stream.records {
+            count += 1;
+            let row = typer.convert(values?, count)?;
+            statement.execute(params_from_iter(row.into_iter().map(to_sql_value)))?;
+        }
+    }
+    tx.commit()?;
+    Ok(count)
+}
+
+/// 执行查询，把结果集转换成文本数据流；NULL 输出为空字符串
+pub fn run_query(conn: &Connection, sql: &str) -> Result<RecordStream<'static>> {
+    let mut statement = conn.prepare(sql)?;
+    let headers: Vec<String> = statement
+        .column_names()
+        .into_iter()
+        .map(String::from)
+        .collect();
+    if let Some(name) = duplicate_column(&headers) {
+        bail!("query returns duplicate column {}, rename it with AS", name);
+    }
+    let width = headers.len();
+    let mut rows = statement.query([])?;
+    let mut records = Vec::new();
+    while let Some(row) = rows.next()? {
+        let values = (0..width)
+            .map(|i| Ok(from_sql_value(row.get_ref(i)?)))
+            .collect::<Result<Vec<_>>>()?;
+        records.push(Ok(values));
+    }
+    Ok(RecordStream::new(headers, records.into_iter()))
+}
+
+fn quote_ident(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+fn sql_type(column_type: ColumnType) -> &'static str {
+    match column_type {
+        ColumnType::Int | ColumnType::Bool => "INTEGER",
+        ColumnType::Float => "REAL",
+        _ => "TEXT",
+    }
+}
+
+/// 布尔值按 SQLite 的惯例存为 0/1，日期和嵌套值存为文本
+fn to_sql_value(value: Value) -> SqlValue {
+    match value {
+        Value::Null => SqlValue::Null,
+        Value::Bool(b) => SqlValue::Integer(b as i64),
+        Value::Int(i) => SqlValue::Integer(i),
+        Value::Float(f) => SqlValue::Real(f),
+        Value::String(s) => SqlValue::Text(s),
+        other => SqlValue::Text(other.to_string()),
+    }
+}
+
+fn from_sql_value(value: ValueRef) -> String {
+    match value {
+        ValueRef::Null => String::new(),
+        ValueRef::Integer(i) => i.to_string(),
+        ValueRef::Real(f) => f.to_string(),
+        ValueRef::Text(text) | ValueRef::Blob(text) => String::from_utf8_lossy(text).into_owned(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stream(rows: &[[&str; 3]]) -> RecordStream<'static> {
+        RecordStream::from_rows(&["id", "car", "city"], rows)
+    }
+
+    #[test]
+    fn test_group_by_query() -> Result<()> {
+        let conn = Connection::open_in_memory()?;
+        let rows = [
+            ["1", "Ford", "Paris"],
+            ["2", "Audi", "Paris"],
+            ["10", "Ford", ""],
+            ["3", "Ford", "Berlin"],
+        ];
+        assert_eq!(load_table(&conn, "input", stream(&rows), true)?, 4);
+        let cities = [["Paris", "France", ""], ["Berlin", "Germany", ""]];
+        load_table(&conn, "cities", stream(&cities), true)?;
+
+        let result = run_query(
+            &conn,
+            "SELECT car, count(*), max(id) FROM input GROUP BY car ORDER BY 2 DESC",
+        )?;
+        assert_eq!(result.headers, vec!["car", "count(*)", "max(id)"]);
+        let rows: Vec<Vec<String>> = result.records.collect::<Result<_>>()?;
+        assert_eq!(rows, vec![vec!["Ford", "3", "10"], vec!["Audi", "1", "2"]]);
+
+        // 整数列按数值比较，多个表之间可以 JOIN
+        let result = run_query(
+            &conn,
+            "SELECT i.id, c.car AS country FROM input i JOIN cities c ON c.id = i.city \
+             WHERE i.id > 2 ORDER BY i.id",
+        )?;
+        let rows: Vec<Vec<String>> = result.records.collect::<Result<_>>()?;
+        assert_eq!(rows, vec![vec!["3", "Germany"]]);
+        assert!(run_query(&conn, "SELECT * FROM missing").is_err());
+
+        let sql = "SELECT i.id, c.id FROM input i JOIN cities c ON c.id = i.city";
+        let err = run_query(&conn, sql).err().unwrap();
+        assert!(err.to_string().contains("duplicate column id"), "{}", err);
+        Ok(())
+    }
+
+    #[test]
+    fn test_table_name() {
+        assert_eq!(table_name("data/MOCK_DATA.csv"), "MOCK_DATA");
+        assert_eq!(table_name("input.csv.gz"), "input");
+        assert_eq!(table_name("-"), "stdin");
+        assert_eq!(table_name("data/.hidden.csv"), ".hidden.csv");
+    }
+}
@@ -0,0 +1,201 @@
+use std::io::Read;
+
+use anyhow::{Context, Result, anyhow, bail};
+use indexmap::{IndexMap, IndexSet};
+use serde_json::Value as JsonValue;
+
+use crate::cli::csv::InputFormat;
+use crate::process::csv_generate::RecordStream;
+
+// JSON/YAML/TOML -> CSV：读取记录数组，嵌套对象展开成 `a.b`、数组展开成 `a[0]` 形式的列
+
+/// 读取整个文档并展开成记录流；表头是所有记录字段的并集，按首次出现的顺序排列。
+/// `separator` 是嵌套对象键之间的连接符，与 --unflatten 使用的一致。
+pub fn open_document_stream<'a, R: Read>(
+    mut reader: R,
+    format: InputFormat,
+    root_key: &str,
+    separator: &str,
+) -> Result<RecordStream<'a>> {
+    let mut content = String::new();
+    reader
+        .read_to_string(&mut content)
+        .context("read input document error")?;
+    let document = parse_document(&content, format)?;
+    let items = find_records(document, root_key)?;
+
+    let mut headers = IndexSet::new();
+    let mut rows = Vec::with_capacity(items.len());
+    for item in items {
+        let mut row = IndexMap::new();
+        match item {
+            JsonValue::Object(_) | JsonValue::Array(_) => {
+                flatten_value("", &item, separator, &mut row)
+            }
+            // 标量数组：每个元素作为 value 列的一行
+            other => flatten_value("value", &other, separator, &mut row),
+        }
+        headers.extend(row.keys().cloned());
+        rows.push(row);
+    }
+
+    let headers: Vec<String> = headers.into_iter().collect();
+    let columns = headers.clone();
+    let records = rows.into_iter().map(move |mut row| {
+        Ok(columns
+            .iter()
+            .map(|h| row.swap_remove(h).unwrap_or_default())
+            .collect())
+    });
+    Ok(RecordStream::new(headers, records))
+}
+
+fn parse_document(content: &str, format: InputFormat) -> Result<JsonValue> {
+    let document = match format {
+        InputFormat::Json => serde_json::from_str(content).context("parse json input error")?,
+        InputFormat::Yaml => serde_yaml::from_str(content).context("parse yaml input error")?,
+        InputFormat::Toml => {
+            let value: toml::Value = toml::from_str(content).context("parse toml input error")?;
+            toml_to_json(value)
+        }
+        InputFormat::Csv => bail!("csv input is not a structured document"),
+        InputFormat::Sheet => bail!("spreadsheet input is not a structured document"),
+    };
+    Ok(document)
+}
+
+fn toml_to_json(value: toml::Value) -> JsonValue {
+    match value {
+        toml::Value::String(s) => JsonValue::String(s),
+        toml::Value::Integer(i) => JsonValue::from(i),
+        toml::Value::Float(f) => JsonValue::from(f),
+        toml::Value::Boolean(b) => JsonValue::Bool(b),
+        toml::Value::Datetime(dt) => JsonValue::String(dt.to_string()),
+        toml::Value::Array(items) => {
+            JsonValue::Array(items.into_iter().map(toml_to_json).collect())
+        }
+        toml::Value::Table(table) => JsonValue::Object(
+            table
+                .into_iter()
+                .map(|(k, v)| (k, toml_to_json(v)))
+                .collect(),
+        ),
+    }
+}
+
+/// 顶层是数组时直接使用；是对象时取 `root_key` 下的数组，
+/// 没有该键但只有一个数组字段时使用这个字段
+fn find_records(document: JsonValue, root_key: &str) -> Result<Vec<JsonValue>> {
+    match document {
+        JsonValue::Array(items) => Ok(items),
+        JsonValue::Object(mut map) => {
+            if let Some(value) = map.swap_remove(root_key) {
+                return match value {
+                    JsonValue::Array(items) => Ok(items),
+                    _ => Err(anyhow!("value under root key {} is not an array", root_key)),
+                };
+            }
+            let mut arrays = map.into_iter().filter(|(_, v)| v.is_array());
+            match (arrays.next(), arrays.next()) {
+                (Some((_, JsonValue::Array(items))), None) => Ok(items),
+                _ => Err(anyhow!(
+                    "cannot find a record array: expected a top-level array or an array under key {}",
+                    root_key
+                )),
+            }
+        }
+        _ => bail!("expected a top-level array or object in the input document"),
+    }
+}
+
+/// 把嵌套的 JSON 值展开成 `列名 -> 文本` 的形式
+pub fn flatten_value(
+    prefix: &str,
+    value: &JsonValue,
+    separator: &str,
+    out: &mut IndexMap<String, String>,
+) {
+    match value {
+        JsonValue::Object(map) if !map.is_empty() => {
+            for (key, child) in map {
+                let path = if prefix.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{}{}{}", prefix, separator, key)
+                };
+                flatten_value(&path, child, separator, out);
+            }
+        }
+        JsonValue::Array(items) if !items.is_empty() => {
+            for (i, child) in items.iter().enumerate() {
+                flatten_value(&format!("{}[{}]", prefix, i), child, separator, out);
+            }
+        }
+        JsonValue::Object(_) | JsonValue::Array(_) | JsonValue::Null => {
+            if !prefix.is_empty() {
+                out.insert(prefix.to_string(), String::new());
+            }
+        }
+        JsonValue::String(s) => {
+            out.insert(prefix.to_string(), s.clone());
+        }
+        JsonValue::Number(n) => {
+            out.insert(prefix.to_string(), n.to_string());
+        }
+        JsonValue::Bool(b) => {
+            out.insert(prefix.to_string(), b.to_string());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn collect(stream: RecordStream) -> Result<(Vec<String>, Vec<Vec<String>>)> {
+        let headers = stream.headers;
+        let rows = stream.records.collect::<Result<_>>()?;
+        Ok((headers, rows))
+    }
+
+    #[test]
+    fn test_json_array_with_nested_objects() -> Result<()> {
+        let data = r#"[
+            {"id": 1, "address": {"city": "Paris", "zip": "75001"}, "tags": ["a", "b"]},
+            {"id": 2, "email": "x@y.z", "address": {"city": "Rome"}}
+        ]"#;
+        let stream = open_document_stream(data.as_bytes(), InputFormat::Json, "items", ".")?;
+        let (headers, rows) = collect(stream)?;
+        assert_eq!(
+            headers,
+            vec![
+                "id",
+                "address.city",
+                "address.zip",
+                "tags[0]",
+                "tags[1]",
+                "email"
+            ]
+        );
+        assert_eq!(rows[0], vec!["1", "Paris", "75001", "a", "b", ""]);
+        assert_eq!(rows[1], vec!["2", "Rome", "", "", "", "x@y.z"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_records_under_root_key() -> Result<()> {
+        let toml_data = "[[items]]\nid = 1\nday = 2024-01-01\n\n[[items]]\nid = 2\n";
+        let stream = open_document_stream(toml_data.as_bytes(), InputFormat::Toml, "items", ".")?;
+        let (headers, rows) = collect(stream)?;
+        assert_eq!(headers, vec!["id", "day"]);
+        assert_eq!(rows[0], vec!["1", "2024-01-01"]);
+
+        let yaml_data = "people:\n  - name: a\n  - name: b\n";
+        let stream = open_document_stream(yaml_data.as_bytes(), InputFormat::Yaml, "items", ".")?;
+        assert_eq!(collect(stream)?.1.len(), 2);
+
+        let bad = r#"{"a": 1}"#;
+        assert!(open_document_stream(bad.as_bytes(), InputFormat::Json, "items", ".").is_err());
+        Ok(())
+    }
+}
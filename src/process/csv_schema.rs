@@ -0,0 +1,470 @@
+use anyhow::{Context, Result, anyhow, bail};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value as JsonValue, json};
+use std::{fmt::Write as _, fs, io::Write};
+
+use crate::cli::csv::{ColumnType, SchemaEmit, SchemaOpts};
+use crate::process::csv_generate::{RecordStream, open_csv_input};
+use crate::process::csv_query::table_name;
+use crate::process::csv_types::TypeInference;
+use crate::utils::get_writer;
+
+/// CSV 的列约束。可以从 TOML 文件读取：
+///
+/// ```toml
+/// [[columns]]
+/// name = "id"
+/// type = "int"
+/// required = true
+/// unique = true
+/// min = 1
+///
+/// [[columns]]
+/// name = "gender"
+/// enum = ["Male", "Female"]
+/// ```
+///
+/// 也可以从 JSON Schema（描述单行对象，或 `items` 为对象的数组）转换而来
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Schema {
+    #[serde(default)]
+    pub columns: Vec<ColumnSchema>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ColumnSchema {
+    pub name: String,
+    #[serde(rename = "type", default, skip_serializing_if = "Option::is_none")]
+    pub column_type: Option<ColumnType>,
+    /// 值不能为空
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub required: bool,
+    /// 值必须匹配的正则表达式（不自动加 ^$）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub regex: Option<String>,
+    #[serde(rename = "enum", default, skip_serializing_if = "Option::is_none")]
+    pub allowed: Option<Vec<String>>,
+    /// 数值下限/上限（含边界）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max: Option<f64>,
+    /// 字符数下限/上限
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_length: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_length: Option<usize>,
+    /// 整列的值不能重复（空值除外）
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub unique: bool,
+}
+
+fn is_false(value: &bool) -> bool {
+    !value
+}
+
+impl Schema {
+    /// 按扩展名读取 schema 文件：`.json` 按 JSON Schema 解析，其余按 TOML 解析
+    pub fn load(path: &str) -> Result<Self> {
+        let content =
+            fs::read_to_string(path).with_context(|| format!("read schema file:{} error", path))?;
+        let schema = if path.to_lowercase().ends_with(".json") {
+            let value: serde_json::Value = serde_json::from_str(&content)
+                .with_context(|| format!("parse json schema:{} error", path))?;
+            Schema::from_json_schema(&value)?
+        } else {
+            toml::from_str(&content).with_context(|| format!("parse toml schema:{} error", path))?
+        };
+        for (i, column) in schema.columns.iter().enumerate() {
+            if schema.columns[..i].iter().any(|c| c.name == column.name) {
+                bail!("column {} is defined twice in schema {}", column.name, path);
+            }
+        }
+        Ok(schema)
+    }
+
+    /// 支持 JSON Schema 中与单列相关的关键字：type、format（date/date-time）、pattern、enum、
+    /// minimum/maximum、minLength/maxLength、required，以及扩展关键字 `x-unique`
+    pub fn from_json_schema(value: &serde_json::Value) -> Result<Self> {
+        // 描述整个文件时取数组元素的 schema
+        let object = match value.get("items") {
+            Some(items) if value.get("type").and_then(|t| t.as_str()) == Some("array") => items,
+            _ => value,
+        };
+        let properties = object
+            .get("properties")
+            .and_then(|p| p.as_object())
+            .ok_or_else(|| anyhow!("json schema has no \"properties\" object"))?;
+        let required: Vec<&str> = object
+            .get("required")
+            .and_then(|r| r.as_array())
+            .map(|names| names.iter().filter_map(|n| n.as_str()).collect())
+            .unwrap_or_default();
+
+        let columns = properties
+            .iter()
+            .map(|(name, property)| {
+                let number = |key: &str| property.get(key).and_then(|v| v.as_f64());
+                let length = |key: &str| {
+                    property
+                        .get(key)
+                        .and_then(|v| v.as_u64())
+                        .map(|n| n as usize)
+                };
+                Ok(ColumnSchema {
+                    name: name.clone(),
+                    column_type: json_schema_type(property)?,
+                    required: required.contains(&name.as_str()),
+                    regex: property
+                        .get("pattern")
+                        .and_then(|p| p.as_str())
+                        .map(String::from),
+                    allowed: property
+                        .get("enum")
+                        .and_then(|e| e.as_array())
+                        .map(|values| {
+                            values
+                                .iter()
+                                .map(|v| match v {
+                                    serde_json::Value::String(s) => s.clone(),
+                                    other => other.to_string(),
+                                })
+                                .collect()
+                        }),
+                    min: number("minimum"),
+                    max: number("maximum"),
+                    min_length: length("minLength"),
+                    max_length: length("maxLength"),
+                    unique: property
+                        .get("x-unique")
+                        .and_then(|u| u.as_bool())
+                        .unwrap_or(false),
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Schema { columns })
+    }
+}
+
+pub fn process_schema(opts: &SchemaOpts) -> Result<()> {
+    let stream = open_csv_input(&opts.input, &opts.read, None)?;
+    let schema = infer_schema(stream, opts.sample_rows)?;
+    let text = match opts.emit {
+        SchemaEmit::Toml => toml::to_string_pretty(&schema).context("serialize schema error")?,
+        SchemaEmit::JsonSchema => {
+            let mut text = serde_json::to_string_pretty(&schema.to_json_schema())?;
+            text.push('\n');
+            text
+        }
+        SchemaEmit::Rust => {
+            let name = match &opts.struct_name {
+                Some(name) => name.clone(),
+                None => type_name(&table_name(&opts.input)),
+            };
+            schema.to_rust_struct(&name)
+        }
+    };
+    let mut output = get_writer(&opts.output)
+        .with_context(|| format!("create output file:{} error", opts.output))?;
+    output.write_all(text.as_bytes())?;
+    output.flush()?;
+    Ok(())
+}
+
+/// 读取数据流（最多 `sample_rows` 行）推断每列的类型；没有空值的列标记为 required，
+/// 全部为空的列不设置类型
+pub fn infer_schema(stream: RecordStream, sample_rows: Option<usize>) -> Result<Schema> {
+    let RecordStream { headers, records } = stream;
+    let mut inference = vec![TypeInference::default(); headers.len()];
+    let mut has_empty = vec![false; headers.len()];
+    let mut rows = 0;
+    for values in records.take(sample_rows.unwrap_or(usize::MAX)) {
+        rows += 1;
+        for (i, raw) in values?.iter().enumerate() {
+            inference[i].observe(raw);
+            has_empty[i] |= raw.is_empty();
+        }
+    }
+    let columns = headers
+        .into_iter()
+        .enumerate()
+        .map(|(i, name)| {
+            let column_type = inference[i].column_type();
+            ColumnSchema {
+                name,
+                column_type: (column_type != ColumnType::Null).then_some(column_type),
+                required: rows > 0 && !has_empty[i],
+                ..Default::default()
+            }
+        })
+        .collect();
+    Ok(Schema { columns })
+}
+
+impl Schema {
+    /// 生成描述整个文件（对象数组）的 JSON Schema，可以被 `from_json_schema` 读回。
+    /// 非字符串列允许为空时类型写成 `["integer", "null"]` 的形式
+    pub fn to_json_schema(&self) -> JsonValue {
+        let mut properties = Map::new();
+        for column in &self.columns {
+            let mut property = Map::new();
+            if let Some(column_type) = column.column_type {
+                let (name, format) = match column_type {
+                    ColumnType::String => ("string", None),
+                    ColumnType::Int => ("integer", None),
+                    ColumnType::Float => ("number", None),
+                    ColumnType::Bool => ("boolean", None),
+                    ColumnType::Date => ("string", Some("date")),
+                    ColumnType::Datetime => ("string", Some("date-time")),
+                    ColumnType::Null => ("null", None),
+                };
+                let nullable = !column.required && !matches!(name, "string" | "null");
+                property.insert(
+                    "type".to_string(),
+                    if nullable {
+                        json!([name, "null"])
+                    } else {
+                        json!(name)
+                    },
+                );
+                if let Some(format) = format {
+                    property.insert("format".to_string(), json!(format));
+                }
+            }
+            let mut insert = |key: &str, value: Option<JsonValue>| {
+                if let Some(value) = value {
+                    property.insert(key.to_string(), value);
+                }
+            };
+            insert("pattern", column.regex.as_ref().map(|r| json!(r)));
+            insert("enum", column.allowed.as_ref().map(|a| json!(a)));
+            insert("minimum", column.min.map(|m| json!(m)));
+            insert("maximum", column.max.map(|m| json!(m)));
+            insert("minLength", column.min_length.map(|m| json!(m)));
+            insert("maxLength", column.max_length.map(|m| json!(m)));
+            insert("x-unique", column.unique.then(|| json!(true)));
+            properties.insert(column.name.clone(), JsonValue::Object(property));
+        }
+        let required: Vec<&str> = self
+            .columns
+            .iter()
+            .filter(|c| c.required)
+            .map(|c| c.name.as_str())
+            .collect();
+        json!({
+            "$schema": "https://json-schema.org/draft/2020-12/schema",
+            "type": "array",
+            "items": {
+                "type": "object",
+                "properties": properties,
+                "required": required,
+            }
+        })
+    }
+
+    /// 生成带 serde 属性的 Rust 结构体，字段名转成 snake_case，与列名不同时加 `rename`。
+    /// 允许为空的非字符串列使用 `Option<T>`，日期按字符串处理
+    pub fn to_rust_struct(&self, name: &str) -> String {
+        let mut code = String::from("use serde::{Deserialize, Serialize};\n\n");
+        code.push_str("#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]\n");
+        let _ = writeln!(code, "pub struct {} {{", name);
+        let mut used: Vec<String> = Vec::new();
+        for column in &self.columns {
+            let mut field = field_name(&column.name);
+            let base = field.clone();
+            let mut suffix = 1;
+            while used.contains(&field) {
+                suffix += 1;
+                field = format!("{}_{}", base, suffix);
+            }
+            if field.trim_start_matches("r#") != column.name {
+                let _ = writeln!(code, "    #[serde(rename = {:?})]", column.name);
+            }
+            let rust_type = match column.column_type {
+                Some(ColumnType::Int) => "i64",
+                Some(ColumnType::Float) => "f64",
+                Some(ColumnType::Bool) => "bool",
+                Some(_) => "String",
+                None => "Option<String>",
+            };
+            if column.required || rust_type.starts_with("Option") || rust_type == "String" {
+                let _ = writeln!(code, "    pub {}: {},", field, rust_type);
+            } else {
+                let _ = writeln!(code, "    pub {}: Option<{}>,", field, rust_type);
+            }
+            used.push(field);
+        }
+        code.push_str("}\n");
+        code
+    }
+}
+
+const RUST_KEYWORDS: &[&str] = &[
+    "as", "async", "await", "break", "const", "continue", "crate", "dyn", "else", "enum", "extern",
+    "false", "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut", "pub",
+    "ref", "return", "self", "static", "struct", "super", "trait", "true", "type", "unsafe", "use",
+    "where", "while", "abstract", "become", "box", "do", "final", "gen", "macro", "override",
+    "priv", "try", "typeof", "unsized", "virtual", "yield",
+];
+
+/// 列名转成 snake_case 的字段名：`First Name` -> `first_name`，`ipAddress` -> `ip_address`
+fn field_name(column: &str) -> String {
+    let mut name = String::new();
+    let mut prev_lower = false;
+    for c in column.chars() {
+        if c.is_ascii_alphanumeric() {
+            if c.is_ascii_uppercase() && prev_lower {
+                name.push('_');
+            }
+            prev_lower = c.is_ascii_lowercase() || c.is_ascii_digit();
+            name.push(c.to_ascii_lowercase());
+        } else {
+            if !name.ends_with('_') {
+                name.push('_');
+            }
+            prev_lower = false;
+        }
+    }
+    let name = name.trim_matches('_');
+    if name.is_empty() || name.starts_with(|c: char| c.is_ascii_digit()) {
+        return format!("column_{}", name);
+    }
+    match name {
+        // 这几个关键字不能写成 r#ident
+        "crate" | "self" | "super" => format!("{}_", name),
+        _ if RUST_KEYWORDS.contains(&name) => format!("r#{}", name),
+        _ => name.to_string(),
+    }
+}
+
+/// 文件名转成 PascalCase 的类型名：`MOCK_DATA` -> `MockData`
+fn type_name(stem: &str) -> String {
+    let name: String = stem
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|part| !part.is_empty())
+        .map(|part| {
+            let mut chars = part.chars();
+            let first = chars.next().unwrap_or_default().to_ascii_uppercase();
+            let rest = chars.as_str();
+            // 全大写的单词（MOCK）转成首字母大写，驼峰的单词（personData）保持原样
+            if rest.chars().all(|c| !c.is_ascii_lowercase()) {
+                format!("{}{}", first, rest.to_ascii_lowercase())
+            } else {
+                format!("{}{}", first, rest)
+            }
+        })
+        .collect();
+    if name.is_empty() || name.starts_with(|c: char| c.is_ascii_digit()) {
+        format!("Record{}", name)
+    } else {
+        name
+    }
+}
+
+fn json_schema_type(property: &serde_json::Value) -> Result<Option<ColumnType>> {
+    // "type": ["integer", "null"] 这种写法取第一个非 null 的类型
+    let name = match property.get("type") {
+        Some(serde_json::Value::String(name)) => Some(name.as_str()),
+        Some(serde_json::Value::Array(names)) => names
+            .iter()
+            .filter_map(|n| n.as_str())
+            .find(|n| *n != "null"),
+        _ => None,
+    };
+    let format = property.get("format").and_then(|f| f.as_str());
+    Ok(match (name, format) {
+        (None, _) => None,
+        (Some("string"), Some("date")) => Some(ColumnType::Date),
+        (Some("string"), Some("date-time")) => Some(ColumnType::Datetime),
+        (Some("string"), _) => Some(ColumnType::String),
+        (Some("integer"), _) => Some(ColumnType::Int),
+        (Some("number"), _) => Some(ColumnType::Float),
+        (Some("boolean"), _) => Some(ColumnType::Bool),
+        (Some("null"), _) => Some(ColumnType::Null),
+        (Some(other), _) => bail!("unsupported json schema type: {}", other),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_toml_and_json_schema() -> Result<()> {
+        let toml_schema: Schema = toml::from_str(
+            r#"
+            [[columns]]
+            name = "id"
+            type = "int"
+            required = true
+            unique = true
+            min = 1
+
+            [[columns]]
+            name = "gender"
+            enum = ["Male", "Female"]
+            "#,
+        )?;
+        let json_schema = Schema::from_json_schema(&serde_json::json!({
+            "type": "array",
+            "items": {
+                "type": "object",
+                "required": ["id"],
+                "properties": {
+                    "id": {"type": "integer", "minimum": 1, "x-unique": true},
+                    "gender": {"enum": ["Male", "Female"]}
+                }
+            }
+        }))?;
+        assert_eq!(toml_schema, json_schema);
+        assert_eq!(toml_schema.columns[0].column_type, Some(ColumnType::Int));
+        assert!(
+            Schema::from_json_schema(&serde_json::json!({"properties": {"a": {"type": "x"}}}))
+                .is_err()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_infer_and_emit_schema() -> Result<()> {
+        let headers = ["id", "First Name", "score", "type", "note"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        let rows = vec![
+            vec!["1", "Ann", "1.5", "a", ""],
+            vec!["2", "Bob", "", "b", ""],
+        ];
+        let rows: Vec<Vec<String>> = rows
+            .into_iter()
+            .map(|row| row.into_iter().map(String::from).collect())
+            .collect();
+        let schema = infer_schema(RecordStream::new(headers, rows.into_iter().map(Ok)), None)?;
+        assert_eq!(schema.columns[0].column_type, Some(ColumnType::Int));
+        assert!(schema.columns[0].required);
+        assert!(!schema.columns[2].required);
+        assert_eq!(schema.columns[4].column_type, None);
+
+        // JSON Schema 和 TOML 都能读回同样的 schema
+        assert_eq!(Schema::from_json_schema(&schema.to_json_schema())?, schema);
+        assert_eq!(
+            toml::from_str::<Schema>(&toml::to_string(&schema)?)?,
+            schema
+        );
+        assert_eq!(
+            schema.to_rust_struct("Record"),
+            "use serde::{Deserialize, Serialize};\n\n\
+             #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]\n\
+             pub struct Record {\n\
+             \x20   pub id: i64,\n\
+             \x20   #[serde(rename = \"First Name\")]\n\
+             \x20   pub first_name: String,\n\
+             \x20   pub score: Option<f64>,\n\
+             \x20   pub r#type: String,\n\
+             \x20   pub note: Option<String>,\n\
+             }\n"
+        );
+        assert_eq!(type_name("MOCK_DATA"), "MockData");
+        Ok(())
+    }
+}
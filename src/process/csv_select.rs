@@ -0,0 +1,136 @@
+use anyhow::{Result, bail};
+
+use crate::cli::csv::ColumnType;
+use crate::process::csv_generate::{RecordStream, duplicate_column, find_column};
+
+/// 列选择、排除与重命名，都在表头上一次性解析，之后每行只做下标映射。
+/// `select` 决定输出列及其顺序，未指定时保留全部列；`rename` 使用原始列名。
+pub fn select_columns<'a>(
+    stream: RecordStream<'a>,
+    select: Option<&[String]>,
+    exclude: &[String],
+    rename: &[(String, String)],
+) -> Result<RecordStream<'a>> {
+    let RecordStream { headers, records } = stream;
+    // 同一列选两次时每行只能取走一次值
+    if let Some(name) = select.and_then(duplicate_column) {
+        bail!("column {} is selected more than once in --select", name);
+    }
+    let mut indices = match select {
+        Some(names) => names
+            .iter()
+            .map(|name| find_column(&headers, name, "--select"))
+            .collect::<Result<Vec<_>>>()?,
+        None => (0..headers.len()).collect(),
+    };
+    let excluded = exclude
+        .iter()
+        .map(|name| find_column(&headers, name, "--exclude"))
+        .collect::<Result<Vec<_>>>()?;
+    indices.retain(|i| !excluded.contains(i));
+
+    let mut output_headers: Vec<String> = indices.iter().map(|&i| headers[i].clone()).collect();
+    for (from, to) in rename {
+        let index = find_column(&headers, from, "--rename")?;
+        let Some(pos) = indices.iter().position(|&i| i == index) else {
+            bail!("--rename refers to column {} which is not selected", from);
+        };
+        output_headers[pos] = to.clone();
+    }
+    if let Some(name) = duplicate_column(&output_headers) {
+        bail!("duplicate output column: {}", name);
+    }
+    if select.is_none() && exclude.is_empty() && rename.is_empty() {
+        return Ok(RecordStream::new(output_headers, records));
+    }
+
+    let records = records.map(move |values| {
+        let mut values = values?;
+        Ok(indices
+            .iter()
+            .map(|&i| std::mem::take(&mut values[i]))
+            .collect())
+    });
+    Ok(RecordStream::new(output_headers, records))
+}
+
+/// 把按原始列名给出的列类型换成 `select_columns` 输出的列名，没有输出的列被丢弃
+pub fn select_column_types(
+    types: &[(String, ColumnType)],
+    select: Option<&[String]>,
+    exclude: &[String],
+    rename: &[(String, String)],
+) -> Vec<(String, ColumnType)> {
+    types
+        .iter()
+        .filter(|(name, _)| {
+            select.is_none_or(|names| names.contains(name)) && !exclude.contains(name)
+        })
+        .map(|(name, column_type)| {
+            let name = rename
+                .iter()
+                .find(|(from, _)| from == name)
+                .map_or(name, |(_, to)| to);
+            (name.clone(), *column_type)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stream() -> RecordStream<'static> {
+        RecordStream::from_rows(
+            &["id", "email", "city", "avatar"],
+            &[["1", "a@b.c", "Paris", "x.png"]],
+        )
+    }
+
+    fn names(items: &[&str]) -> Vec<String> {
+        items.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_select_rename_exclude() -> Result<()> {
+        let select = names(&["city", "email", "id"]);
+        let rename = vec![("email".to_string(), "contact".to_string())];
+        let result = select_columns(stream(), Some(&select), &names(&["id"]), &rename)?;
+        assert_eq!(result.headers, vec!["city", "contact"]);
+        let rows: Vec<Vec<String>> = result.records.collect::<Result<_>>()?;
+        assert_eq!(rows, vec![vec!["Paris", "a@b.c"]]);
+
+        let result = select_columns(stream(), None, &names(&["avatar"]), &[])?;
+        assert_eq!(result.headers, vec!["id", "email", "city"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_unknown_and_duplicate_columns() {
+        let err = select_columns(stream(), Some(&names(&["nope"])), &[], &[])
+            .err()
+            .unwrap();
+        assert!(
+            err.to_string()
+                .contains("available columns: id, email, city, avatar")
+        );
+
+        let rename = vec![("email".to_string(), "city".to_string())];
+        assert!(select_columns(stream(), None, &[], &rename).is_err());
+
+        let select = names(&["id", "id"]);
+        let rename = vec![("id".to_string(), "x".to_string())];
+        let err = select_columns(stream(), Some(&select), &[], &rename)
+            .err()
+            .unwrap();
+        assert!(
+            err.to_string().contains("selected more than once"),
+            "{}",
+            err
+        );
+
+        // 不带任何选项时同样检查重名
+        let duplicated = RecordStream::from_rows(&["id", "id"], &[["1", "2"]]);
+        assert!(select_columns(duplicated, None, &[], &[]).is_err());
+    }
+}
@@ -0,0 +1,264 @@
+use anyhow::{Context, Result, bail};
+use calamine::{Data, DataType, Range, Reader, open_workbook_auto_from_rs};
+use std::io::{Cursor, Read};
+
+use crate::cli::csv::{CellRange, ColumnType, CsvReadArgs};
+use crate::process::csv_generate::{RecordStream, duplicate_column};
+use crate::utils::get_data_reader;
+
+/// 读取表格文件（`-` 表示标准输入）中的一个工作表，`sheet` 为名称或从 1 开始的序号。
+/// 返回数据流和按单元格类型得出的列类型
+pub fn open_sheet_stream(
+    path: &str,
+    sheet: Option<&str>,
+    cell_range: Option<CellRange>,
+    args: &CsvReadArgs,
+) -> Result<(RecordStream<'static>, Vec<(String, ColumnType)>)> {
+    let mut content = Vec::new();
+    get_data_reader(path)
+        .and_then(|mut reader| Ok(reader.read_to_end(&mut content)?))
+        .with_context(|| format!("open input file:{} error", path))?;
+    let mut workbook = open_workbook_auto_from_rs(Cursor::new(content.as_slice()))
+        .with_context(|| format!("open spreadsheet:{} error", path))?;
+    let names = workbook.sheet_names();
+    let name = match sheet {
+        None => names.first().context("spreadsheet has no worksheet")?,
+        Some(sheet) => match names.iter().find(|name| *name == sheet) {
+            Some(name) => name,
+            None => match sheet.parse::<usize>() {
+                Ok(n) if (1..=names.len()).contains(&n) => &names[n - 1],
+                _ => bail!(
+                    "unknown worksheet: {}, available worksheets: {}",
+                    sheet,
+                    names.join(", ")
+                ),
+            },
+        },
+    };
+    let range = workbook
+        .worksheet_range(name)
+        .with_context(|| format!("read worksheet:{} error", name))?;
+    let range = match cell_range {
+        Some(cell_range) => select_range(&range, cell_range),
+        None => range,
+    };
+    sheet_stream(&range, args)
+}
+
+/// 按 --range 截取区域，超出数据末尾的部分不输出
+fn select_range(range: &Range<Data>, cell_range: CellRange) -> Range<Data> {
+    let Some((last_row, last_col)) = range.end() else {
+        return Range::empty();
+    };
+    let (end_row, end_col) = cell_range.end.unwrap_or((last_row, last_col));
+    let end = (end_row.min(last_row), end_col.min(last_col));
+    if cell_range.start.0 > end.0 || cell_range.start.1 > end.1 {
+        return Range::empty();
+    }
+    range.range(cell_range.start, end)
+}
+
+/// 把单元格区域转换成数据流，第一行作为表头（--no-header 时生成 col1..colN）
+pub fn sheet_stream(
+    range: &Range<Data>,
+    args: &CsvReadArgs,
+) -> Result<(RecordStream<'static>, Vec<(String, ColumnType)>)> {
+    let mut rows = range.rows();
+    let width = range.width();
+    let mut headers: Vec<String> = match rows.next() {
+        Some(first) if args.header => first
+            .iter()
+            .enumerate()
+            .map(|(i, cell)| match cell_value(cell).0 {
+                name if name.is_empty() => format!("col{}", i + 1),
+                name => name,
+            })
+            .collect(),
+        _ => {
+            rows = range.rows();
+            (1..=width).map(|i| format!("col{}", i)).collect()
+        }
+    };
+    if let Some(columns) = &args.columns {
+        if !headers.is_empty() && columns.len() != headers.len() {
+            bail!(
+                "--columns has {} names but the input has {} columns",
+                columns.len(),
+                headers.len()
+            );
+        }
+        headers = columns.clone();
+    }
+    if let Some(name) = duplicate_column(&headers) {
+        bail!(
+            "duplicate column name {} in the worksheet, rename columns with --columns",
+            name
+        );
+    }
+
+    let mut types: Vec<Option<ColumnType>> = vec![None; headers.len()];
+    let mut records = Vec::with_capacity(range.height());
+    for row in rows {
+        let mut values = Vec::with_capacity(width);
+        for (cell, column_type) in row.iter().zip(types.iter_mut()) {
+            let (value, cell_type) = cell_value(cell);
+            if let Some(cell_type) = cell_type {
+                *column_type = Some(match *column_type {
+                    None => cell_type,
+                    Some(current) => merge_types(current, cell_type),
+                });
+            }
+            values.push(value);
+        }
+        records.push(values);
+    }
+    let types = headers
+        .iter()
+        .zip(types)
+        .filter_map(|(name, column_type)| Some((name.clone(), column_type?)))
+        .collect();
+    let stream = RecordStream::new(headers, records.into_iter().map(Ok));
+    Ok((stream, types))
+}
+
+/// 单元格的文本和类型，空单元格没有类型。Excel 的数字都是浮点数，没有小数部分时按整数输出；
+/// 日期输出为 2024-01-05，带时间的输出为 2024-01-05 12:00:00
+fn cell_value(cell: &Data) -> (String, Option<ColumnType>) {
+    match cell {
+        Data::Empty => (String::new(), None),
+        Data::String(s) => (s.clone(), Some(ColumnType::String)),
+        Data::Int(i) => (i.to_string(), Some(ColumnType::Int)),
+        Data::Float(f) if f.fract() == 0.0 && f.abs() < 1e15 => {
+            ((*f as i64).to_string(), Some(ColumnType::Int))
+        }
+        Data::Float(f) => (f.to_string(), Some(ColumnType::Float)),
+        Data::Bool(b) => (b.to_string(), Some(ColumnType::Bool)),
+        Data::DateTime(dt) if dt.is_duration() => (cell.to_string(), Some(ColumnType::String)),
+        Data::DateTime(dt) => match cell.as_datetime() {
+            Some(value) if dt.as_f64().fract() == 0.0 => {
+                (value.date().to_string(), Some(ColumnType::Date))
+            }
+            Some(value) => (value.to_string(), Some(ColumnType::Datetime)),
+            None => (cell.to_string(), Some(ColumnType::String)),
+        },
+        Data::DateTimeIso(_) => match (cell.as_datetime(), cell.as_date()) {
+            (Some(value), _) => (value.to_string(), Some(ColumnType::Datetime)),
+            (None, Some(date)) => (date.to_string(), Some(ColumnType::Date)),
+            _ => (cell.to_string(), Some(ColumnType::String)),
+        },
+        _ => (cell.to_string(), Some(ColumnType::String)),
+    }
+}
+
+fn merge_types(a: ColumnType, b: ColumnType) -> ColumnType {
+    match (a, b) {
+        (a, b) if a == b => a,
+        (ColumnType::Int, ColumnType::Float) | (ColumnType::Float, ColumnType::Int) => {
+            ColumnType::Float
+        }
+        (ColumnType::Date, ColumnType::Datetime) | (ColumnType::Datetime, ColumnType::Date) => {
+            ColumnType::Datetime
+        }
+        _ => ColumnType::String,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use calamine::{ExcelDateTime, ExcelDateTimeType};
+
+    fn range() -> Range<Data> {
+        let rows = [
+            vec![
+                Data::String("id".into()),
+                Data::String("price".into()),
+                Data::String("active".into()),
+                Data::String("signup".into()),
+                Data::String("zip".into()),
+            ],
+            vec![
+                Data::Float(1.0),
+                Data::Float(9.5),
+                Data::Bool(true),
+                Data::DateTime(ExcelDateTime::new(
+                    45296.0,
+                    ExcelDateTimeType::DateTime,
+                    false,
+                )),
+                Data::String("00123".into()),
+            ],
+            vec![
+                Data::Int(2),
+                Data::Float(3.0),
+                Data::Empty,
+                Data::DateTime(ExcelDateTime::new(
+                    45296.5,
+                    ExcelDateTimeType::DateTime,
+                    false,
+                )),
+                Data::Float(9000.0),
+            ],
+        ];
+        let mut range = Range::new((0, 0), (2, 4));
+        for (r, row) in rows.into_iter().enumerate() {
+            for (c, cell) in row.into_iter().enumerate() {
+                range.set_value((r as u32, c as u32), cell);
+            }
+        }
+        range
+    }
+
+    #[test]
+    fn test_sheet_stream_types() -> Result<()> {
+        let (stream, types) = sheet_stream(&range(), &CsvReadArgs::default())?;
+        assert_eq!(
+            stream.headers,
+            vec!["id", "price", "active", "signup", "zip"]
+        );
+        let rows: Vec<Vec<String>> = stream.records.collect::<Result<_>>()?;
+        assert_eq!(
+            rows,
+            vec![
+                vec!["1", "9.5", "true", "2024-01-05", "00123"],
+                vec!["2", "3", "", "2024-01-05 12:00:00", "9000"],
+            ]
+        );
+        let types: Vec<ColumnType> = types.into_iter().map(|(_, t)| t).collect();
+        assert_eq!(
+            types,
+            vec![
+                ColumnType::Int,
+                ColumnType::Float,
+                ColumnType::Bool,
+                ColumnType::Datetime,
+                ColumnType::String
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_select_range() -> Result<()> {
+        let cell_range: CellRange = "B2:Z9".parse()?;
+        let args = CsvReadArgs {
+            header: false,
+            ..Default::default()
+        };
+        let (stream, _) = sheet_stream(&select_range(&range(), cell_range), &args)?;
+        assert_eq!(stream.headers, vec!["col1", "col2", "col3", "col4"]);
+        assert_eq!(stream.records.count(), 2);
+
+        let cell_range: CellRange = "c3".parse()?;
+        assert_eq!(cell_range.start, (2, 2));
+        assert_eq!(select_range(&range(), cell_range).get_size(), (1, 3));
+        assert!("F1:A1".parse::<CellRange>().is_err());
+        assert!("A0".parse::<CellRange>().is_err());
+
+        // 表头单元格重名时报错
+        let mut duplicated = range();
+        duplicated.set_value((0, 1), Data::String("id".into()));
+        assert!(sheet_stream(&duplicated, &CsvReadArgs::default()).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,351 @@
+use anyhow::{Context, Result};
+use csv::{ReaderBuilder, WriterBuilder};
+use std::{
+    cmp::Ordering,
+    collections::{BinaryHeap, HashSet},
+    fs::File,
+    io::{BufReader, BufWriter, Seek, SeekFrom},
+    rc::Rc,
+};
+
+use crate::process::csv_generate::{RecordStream, find_column};
+
+/// 一次归并最多同时打开的临时文件数，块更多时先分组归并成中间文件
+const MERGE_FAN_IN: usize = 64;
+
+/// 一个排序键：列下标与是否降序
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SortKey {
+    pub column: usize,
+    pub descending: bool,
+}
+
+/// 解析 `--sort-by` 的列名，`-` 前缀表示降序，例如 city,-id
+pub fn parse_sort_keys(specs: &[String], headers: &[String]) -> Result<Vec<SortKey>> {
+    specs
+        .iter()
+        .map(|spec| {
+            let (name, descending) = match spec.strip_prefix('-') {
+                Some(name) => (name, true),
+                None => (spec.strip_prefix('+').unwrap_or(spec), false),
+            };
+            Ok(SortKey {
+                column: find_column(headers, name, "--sort-by")?,
+                descending,
+            })
+        })
+        .collect()
+}
+
+/// 比较单个字段：两边都像数字时按数值比较，否则按文本比较；
+/// 数字总是排在非数字之前，这样混合列也能得到一致的全序
+pub fn compare_values(a: &str, b: &str) -> Ordering {
+    match (parse_number(a), parse_number(b)) {
+        (Some(x), Some(y)) => x.total_cmp(&y).then_with(|| a.cmp(b)),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => a.cmp(b),
+    }
+}
+
+fn parse_number(raw: &str) -> Option<f64> {
+    let raw = raw.trim();
+    if raw.is_empty() {
+        return None;
+    }
+    raw.parse::<f64>().ok().filter(|n| n.is_finite())
+}
+
+pub fn compare_rows(a: &[String], b: &[String], keys: &[SortKey]) -> Ordering {
+    for key in keys {
+        let ordering = compare_values(&a[key.column], &b[key.column]);
+        let ordering = if key.descending {
+            ordering.reverse()
+        } else {
+            ordering
+        };
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+    Ordering::Equal
+}
+
+/// 按 `--sort-by` 排序（稳定排序）。内存中最多缓存 `buffer_rows` 行，
+/// 超出后把已排序的块写入临时文件，最后对所有块做多路归并
+pub fn sort_stream<'a>(
+    stream: RecordStream<'a>,
+    specs: &[String],
+    buffer_rows: usize,
+) -> Result<RecordStream<'a>> {
+    let keys = parse_sort_keys(specs, &stream.headers)?;
+    sort_with_fan_in(stream, keys, buffer_rows, MERGE_FAN_IN)
+}
+
+/// 临时文件按层管理：某一层攒满 `fan_in` 个块就归并成上一层的一个块，
+/// 同时打开的临时文件数因此有上界。上层的块总是早于下层的块，相等的行仍保持原始顺序
+fn sort_with_fan_in<'a>(
+    stream: RecordStream<'a>,
+    keys: Vec<SortKey>,
+    buffer_rows: usize,
+    fan_in: usize,
+) -> Result<RecordStream<'a>> {
+    let RecordStream { headers, records } = stream;
+    let buffer_rows = buffer_rows.max(1);
+    let fan_in = fan_in.max(2);
+
+    let mut levels: Vec<Vec<File>> = Vec::new();
+    let mut buffer = Vec::new();
+    for values in records {
+        buffer.push(values?);
+        if buffer.len() >= buffer_rows {
+            buffer.sort_by(|a, b| compare_rows(a, b, &keys));
+            let chunk = spill_chunk(buffer.iter().map(Ok))?;
+            push_chunk(&mut levels, chunk, &keys, fan_in)?;
+            buffer.clear();
+        }
+    }
+    buffer.sort_by(|a, b| compare_rows(a, b, &keys));
+
+    // 全部数据都在内存里时不需要归并
+    if levels.is_empty() {
+        return Ok(RecordStream::new(headers, buffer.into_iter().map(Ok)));
+    }
+    if !buffer.is_empty() {
+        let chunk = spill_chunk(buffer.iter().map(Ok))?;
+        push_chunk(&mut levels, chunk, &keys, fan_in)?;
+    }
+    let mut chunks: Vec<File> = levels.into_iter().rev().flatten().collect();
+    // 各层剩下的块加起来仍可能超过 fan_in，再按相邻分组归并
+    while chunks.len() > fan_in {
+        let mut merged = Vec::with_capacity(chunks.len().div_ceil(fan_in));
+        let mut rest = chunks.into_iter().peekable();
+        while rest.peek().is_some() {
+            let group: Vec<File> = rest.by_ref().take(fan_in).collect();
+            merged.push(spill_chunk(MergeChunks::new(group, keys.clone())?)?);
+        }
+        chunks = merged;
+    }
+    let merge = MergeChunks::new(chunks, keys)?;
+    Ok(RecordStream::new(headers, merge))
+}
+
+fn push_chunk(
+    levels: &mut Vec<Vec<File>>,
+    mut chunk: File,
+    keys: &[SortKey],
+    fan_in: usize,
+) -> Result<()> {
+    for level in 0.. {
+        if levels.len() == level {
+            levels.push(Vec::new());
+        }
+        levels[level].push(chunk);
+        if levels[level].len() < fan_in {
+            break;
+        }
+        let group = std::mem::take(&mut levels[level]);
+        chunk = spill_chunk(MergeChunks::new(group, keys.to_vec())?)?;
+    }
+    Ok(())
+}
+
+/// 把一个已排序的块写入匿名临时文件（进程退出或文件关闭时自动删除）
+fn spill_chunk<R, T>(rows: impl IntoIterator<Item = Result<R>>) -> Result<File>
+where
+    R: IntoIterator<Item = T>,
+    T: AsRef<[u8]>,
+{
+    let file = tempfile::tempfile().context("create temporary file for sorting error")?;
+    let mut writer = WriterBuilder::new()
+        .has_headers(false)
+        .from_writer(BufWriter::new(file));
+    for row in rows {
+        writer
+            .write_record(row?)
+            .context("write temporary sort file error")?;
+    }
+    let mut file = writer
+        .into_inner()
+        .map_err(|e| e.into_error())
+        .context("write temporary sort file error")?
+        .into_inner()
+        .map_err(|e| e.into_error())
+        .context("write temporary sort file error")?;
+    file.seek(SeekFrom::Start(0))?;
+    Ok(file)
+}
+
+type ChunkRecords = csv::StringRecordsIntoIter<BufReader<File>>;
+
+/// 归并堆中的一项；`chunk` 用于相等时保持原始顺序，实现稳定排序
+struct HeapItem {
+    row: Vec<String>,
+    chunk: usize,
+    keys: Rc<[SortKey]>,
+}
+
+impl Ord for HeapItem {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // BinaryHeap 是最大堆，这里反转顺序让最小的行先出堆
+        compare_rows(&other.row, &self.row, &self.keys).then_with(|| other.chunk.cmp(&self.chunk))
+    }
+}
+
+impl PartialOrd for HeapItem {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for HeapItem {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for HeapItem {}
+
+struct MergeChunks {
+    readers: Vec<ChunkRecords>,
+    heap: BinaryHeap<HeapItem>,
+    keys: Rc<[SortKey]>,
+}
+
+impl MergeChunks {
+    fn new(chunks: Vec<File>, keys: Vec<SortKey>) -> Result<Self> {
+        let mut merge = Self {
+            readers: chunks
+                .into_iter()
+                .map(|file| {
+                    ReaderBuilder::new()
+                        .has_headers(false)
+                        .from_reader(BufReader::new(file))
+                        .into_records()
+                })
+                .collect(),
+            heap: BinaryHeap::new(),
+            keys: keys.into(),
+        };
+        for chunk in 0..merge.readers.len() {
+            merge.refill(chunk)?;
+        }
+        Ok(merge)
+    }
+
+    fn refill(&mut self, chunk: usize) -> Result<()> {
+        if let Some(record) = self.readers[chunk].next() {
+            let record = record.context("read temporary sort file error")?;
+            self.heap.push(HeapItem {
+                row: record.iter().map(String::from).collect(),
+                chunk,
+                keys: Rc::clone(&self.keys),
+            });
+        }
+        Ok(())
+    }
+}
+
+impl Iterator for MergeChunks {
+    type Item = Result<Vec<String>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let item = self.heap.pop()?;
+        if let Err(e) = self.refill(item.chunk) {
+            return Some(Err(e));
+        }
+        Some(Ok(item.row))
+    }
+}
+
+/// 按 `--dedupe-by` 的列去重，保留每组键第一次出现的行；
+/// 只在内存中保存已见过的键，而不是整行
+pub fn dedupe_stream<'a>(stream: RecordStream<'a>, columns: &[String]) -> Result<RecordStream<'a>> {
+    let RecordStream { headers, records } = stream;
+    let indices = columns
+        .iter()
+        .map(|name| find_column(&headers, name, "--dedupe-by"))
+        .collect::<Result<Vec<_>>>()?;
+    let mut seen = HashSet::new();
+    let records = records.filter(move |values| match values {
+        Ok(values) => {
+            let key: Vec<String> = indices.iter().map(|&i| values[i].clone()).collect();
+            seen.insert(key)
+        }
+        Err(_) => true,
+    });
+    Ok(RecordStream::new(headers, records))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stream(rows: &[[&str; 3]]) -> RecordStream<'static> {
+        RecordStream::from_rows(&["id", "city", "email"], rows)
+    }
+
+    fn ids(stream: RecordStream) -> Result<Vec<String>> {
+        stream
+            .records
+            .map(|values| Ok(values?[0].clone()))
+            .collect()
+    }
+
+    fn rows() -> Vec<[&'static str; 3]> {
+        vec![
+            ["10", "Paris", "a@x.com"],
+            ["9", "Berlin", "b@x.com"],
+            ["2", "Paris", "c@x.com"],
+            ["100", "Berlin", "a@x.com"],
+            ["x", "Paris", "d@x.com"],
+            ["", "Berlin", "e@x.com"],
+        ]
+    }
+
+    #[test]
+    fn test_sort_numeric_and_descending() -> Result<()> {
+        let specs = vec!["city".to_string(), "-id".to_string()];
+        let sorted = sort_stream(stream(&rows()), &specs, 1000)?;
+        assert_eq!(ids(sorted)?, vec!["", "100", "9", "x", "10", "2"]);
+        let sorted = sort_stream(stream(&rows()), &["id".to_string()], 1000)?;
+        assert_eq!(ids(sorted)?, vec!["2", "9", "10", "100", "", "x"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_external_sort_matches_in_memory() -> Result<()> {
+        let specs = vec!["city".to_string(), "id".to_string()];
+        let expected = ids(sort_stream(stream(&rows()), &specs, 1000)?)?;
+        // 缓冲很小时会溢写多个临时文件，归并结果应与内存排序一致（包括稳定性）
+        for buffer_rows in 1..=4 {
+            let sorted = sort_stream(stream(&rows()), &specs, buffer_rows)?;
+            assert_eq!(ids(sorted)?, expected);
+        }
+        let stable = sort_stream(stream(&rows()), &["city".to_string()], 2)?;
+        assert_eq!(ids(stable)?, vec!["9", "100", "", "10", "2", "x"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_sort_with_bounded_fan_in() -> Result<()> {
+        let many: Vec<[&str; 3]> = (0..5).flat_map(|_| rows()).collect();
+        let specs = vec!["city".to_string(), "-id".to_string()];
+        let keys = parse_sort_keys(&specs, &stream(&[]).headers)?;
+        let expected = ids(sort_stream(stream(&many), &specs, 1000)?)?;
+        // fan-in 为 2 时 30 个块需要多层中间归并，结果与稳定的内存排序一致
+        for buffer_rows in [1, 2, 7] {
+            let sorted = sort_with_fan_in(stream(&many), keys.clone(), buffer_rows, 2)?;
+            assert_eq!(ids(sorted)?, expected);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_dedupe_keeps_first() -> Result<()> {
+        let deduped = dedupe_stream(stream(&rows()), &["email".to_string()])?;
+        assert_eq!(ids(deduped)?, vec!["10", "9", "2", "x", ""]);
+        assert!(sort_stream(stream(&rows()), &["-age".to_string()], 10).is_err());
+        Ok(())
+    }
+}
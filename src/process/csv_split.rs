@@ -0,0 +1,240 @@
+use anyhow::{Context, Result, bail};
+use csv::{Writer, WriterBuilder};
+use indexmap::IndexMap;
+use std::{
+    collections::{HashMap, HashSet},
+    fs::{self, File, OpenOptions},
+    io::BufWriter,
+    path::{Path, PathBuf},
+};
+
+use crate::cli::csv::SplitOpts;
+use crate::process::csv_generate::{RecordStream, find_column, open_csv_input};
+use crate::process::csv_query::table_name;
+
+#[derive(Debug, Clone, Copy)]
+pub enum SplitMode<'a> {
+    /// 每个文件最多 N 行
+    Rows(usize),
+    /// 按列值拆分，每个值一个文件
+    By(&'a str),
+}
+
+/// 拆分出的文件使用的 CSV 格式，与输入保持一致
+#[derive(Debug, Clone)]
+pub struct SplitOutput<'a> {
+    pub dir: &'a Path,
+    pub prefix: &'a str,
+    pub delimiter: u8,
+    pub write_header: bool,
+}
+
+pub fn process_split(opts: &SplitOpts) -> Result<()> {
+    let mode = match (opts.rows, &opts.by) {
+        (Some(0), _) => bail!("--rows must be greater than 0"),
+        (Some(rows), _) => SplitMode::Rows(rows),
+        (None, Some(column)) => SplitMode::By(column),
+        (None, None) => bail!("either --rows or --by is required"),
+    };
+    let stream = open_csv_input(&opts.input, &opts.read, None)?;
+    fs::create_dir_all(&opts.output_dir)
+        .with_context(|| format!("create output directory:{} error", opts.output_dir))?;
+    let prefix = match &opts.prefix {
+        Some(prefix) => prefix.clone(),
+        None => table_name(&opts.input),
+    };
+    let output = SplitOutput {
+        dir: Path::new(&opts.output_dir),
+        prefix: &prefix,
+        delimiter: opts.read.delimiter,
+        write_header: opts.read.header || opts.read.columns.is_some(),
+    };
+    let files = split_stream(stream, mode, &output)?;
+    eprintln!("wrote {} files to {}", files.len(), opts.output_dir);
+    Ok(())
+}
+
+/// 按列值拆分时最多同时打开的文件数，超出后关闭最久没有写入的文件，再写入时以追加方式打开
+const MAX_OPEN_FILES: usize = 128;
+
+/// 按 `mode` 把数据流写入多个 CSV 文件，每个文件都带表头，返回按创建顺序排列的文件路径
+pub fn split_stream(
+    stream: RecordStream,
+    mode: SplitMode,
+    output: &SplitOutput,
+) -> Result<Vec<PathBuf>> {
+    split_with_limit(stream, mode, output, MAX_OPEN_FILES)
+}
+
+fn split_with_limit(
+    stream: RecordStream,
+    mode: SplitMode,
+    output: &SplitOutput,
+    max_open: usize,
+) -> Result<Vec<PathBuf>> {
+    let (by, max_open) = match mode {
+        SplitMode::By(column) => (
+            Some(find_column(&stream.headers, column, "--by")?),
+            max_open,
+        ),
+        // 按行拆分时新文件开始后上一个文件不会再写入，及时关闭
+        SplitMode::Rows(_) => (None, 1),
+    };
+    let mut files = Vec::new();
+    let mut file_names: HashSet<String> = HashSet::new();
+    let mut paths: HashMap<String, PathBuf> = HashMap::new();
+    // 按最近写入的顺序排列，最久没有写入的在最前面
+    let mut writers: IndexMap<String, Writer<BufWriter<File>>> = IndexMap::new();
+    for (i, values) in stream.records.enumerate() {
+        let values = values?;
+        let key = match (mode, by) {
+            (SplitMode::Rows(rows), _) => (i / rows + 1).to_string(),
+            (_, Some(index)) => values[index].clone(),
+            _ => unreachable!("--by column is resolved above"),
+        };
+        if let Some(index) = writers.get_index_of(&key) {
+            writers.move_index(index, writers.len() - 1);
+        } else {
+            if writers.len() >= max_open
+                && let Some((_, mut oldest)) = writers.shift_remove_index(0)
+            {
+                oldest.flush().context("write split file error")?;
+            }
+            let writer = match paths.get(&key) {
+                Some(path) => {
+                    let file = OpenOptions::new()
+                        .append(true)
+                        .open(path)
+                        .with_context(|| format!("open output file:{} error", path.display()))?;
+                    csv_writer(BufWriter::new(file), output.delimiter)
+                }
+                None => {
+                    let stem = match mode {
+                        SplitMode::Rows(_) => format!("{:0>4}", key),
+                        SplitMode::By(_) => file_stem(&key),
+                    };
+                    let mut name = format!("{}_{}", output.prefix, stem);
+                    // 不同的值清理后可能得到相同的文件名
+                    let base = name.clone();
+                    let mut suffix = 1;
+                    while !file_names.insert(name.clone()) {
+                        suffix += 1;
+                        name = format!("{}_{}", base, suffix);
+                    }
+                    let path = output.dir.join(format!("{}.csv", name));
+                    let file = File::create(&path)
+                        .with_context(|| format!("create output file:{} error", path.display()))?;
+                    let mut writer = csv_writer(BufWriter::new(file), output.delimiter);
+                    if output.write_header {
+                        writer.write_record(&stream.headers)?;
+                    }
+                    paths.insert(key.clone(), path.clone());
+                    files.push(path);
+                    writer
+                }
+            };
+            writers.insert(key, writer);
+        }
+        let (_, writer) = writers.last_mut().expect("writer is inserted above");
+        writer.write_record(&values)?;
+    }
+    for (_, mut writer) in writers {
+        writer.flush().context("write split file error")?;
+    }
+    Ok(files)
+}
+
+fn csv_writer(writer: BufWriter<File>, delimiter: u8) -> Writer<BufWriter<File>> {
+    WriterBuilder::new()
+        .delimiter(delimiter)
+        .from_writer(writer)
+}
+
+/// 列值转换成安全的文件名片段：只保留字母、数字、`-` 和 `_`
+fn file_stem(value: &str) -> String {
+    if value.is_empty() {
+        return "empty".to_string();
+    }
+    value
+        .chars()
+        .take(100)
+        .map(|c| {
+            if c.is_alphanumeric() || c == '-' || c == '_' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stream() -> RecordStream<'static> {
+        let rows = [
+            ["1", "Ford"],
+            ["2", "Audi"],
+            ["3", "Ford"],
+            ["4", "Land Rover"],
+            ["5", "Land/Rover"],
+        ];
+        RecordStream::from_rows(&["id", "car"], &rows)
+    }
+
+    fn output(dir: &Path) -> SplitOutput<'_> {
+        SplitOutput {
+            dir,
+            prefix: "cars",
+            delimiter: b',',
+            write_header: true,
+        }
+    }
+
+    #[test]
+    fn test_split_by_rows() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let files = split_stream(stream(), SplitMode::Rows(2), &output(dir.path()))?;
+        let names: Vec<_> = files.iter().map(|f| f.file_name().unwrap()).collect();
+        assert_eq!(
+            names,
+            vec!["cars_0001.csv", "cars_0002.csv", "cars_0003.csv"]
+        );
+        assert_eq!(
+            fs::read_to_string(&files[1])?,
+            "id,car\n3,Ford\n4,Land Rover\n"
+        );
+        assert_eq!(fs::read_to_string(&files[2])?, "id,car\n5,Land/Rover\n");
+        Ok(())
+    }
+
+    #[test]
+    fn test_split_by_column() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let files = split_stream(stream(), SplitMode::By("car"), &output(dir.path()))?;
+        let names: Vec<_> = files.iter().map(|f| f.file_name().unwrap()).collect();
+        assert_eq!(
+            names,
+            vec![
+                "cars_Ford.csv",
+                "cars_Audi.csv",
+                "cars_Land_Rover.csv",
+                "cars_Land_Rover_2.csv"
+            ]
+        );
+        assert_eq!(fs::read_to_string(&files[0])?, "id,car\n1,Ford\n3,Ford\n");
+        assert!(split_stream(stream(), SplitMode::By("x"), &output(dir.path())).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_split_reopens_closed_files() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let files = split_with_limit(stream(), SplitMode::By("car"), &output(dir.path()), 1)?;
+        assert_eq!(files.len(), 4);
+        assert_eq!(fs::read_to_string(&files[0])?, "id,car\n1,Ford\n3,Ford\n");
+        assert_eq!(fs::read_to_string(&files[1])?, "id,car\n2,Audi\n");
+        Ok(())
+    }
+}
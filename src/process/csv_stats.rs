@@ -0,0 +1,342 @@
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::{
+    collections::HashMap,
+    hash::{BuildHasher, BuildHasherDefault, DefaultHasher},
+    io::Write,
+};
+
+use crate::cli::csv::{ColumnType, StatsFormat, StatsOpts};
+use crate::process::csv_generate::{RecordStream, open_csv_stream};
+use crate::process::csv_types::TypeInference;
+use crate::process::csv_writer::{truncate_cell, write_text_table};
+use crate::utils::{get_data_reader, get_writer};
+
+/// 单列的统计结果，JSON 输出直接序列化这个结构
+#[derive(Debug, Serialize)]
+pub struct ColumnStats {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub column_type: ColumnType,
+    /// 总行数（包括空值）
+    pub count: usize,
+    pub empty: usize,
+    /// 不同的非空值个数；`distinct_estimated` 为 true 时是 HyperLogLog 估算值
+    pub distinct: usize,
+    pub distinct_estimated: bool,
+    pub min: Option<String>,
+    pub max: Option<String>,
+    pub mean: Option<f64>,
+    pub median: Option<f64>,
+    pub stddev: Option<f64>,
+    pub top: Vec<TopValue>,
+    pub max_length: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TopValue {
+    pub value: String,
+    pub count: usize,
+}
+
+pub fn process_stats(opts: &StatsOpts) -> Result<()> {
+    let input = get_data_reader(&opts.input)
+        .with_context(|| format!("open input file:{} error", opts.input))?;
+    let stream = open_csv_stream(input, &opts.read)?;
+    let stats = profile_stream(stream, opts.top, opts.distinct_limit)?;
+
+    let mut output = get_writer(&opts.output)
+        .with_context(|| format!("create output file:{} error", opts.output))?;
+    match opts.format {
+        StatsFormat::Json => {
+            serde_json::to_writer_pretty(&mut output, &stats)?;
+            writeln!(output)?;
+        }
+        StatsFormat::Table => write_table(&mut output, &stats)?,
+    }
+    output.flush()?;
+    Ok(())
+}
+
+/// 单次遍历数据流计算每列的统计信息。
+/// 数值列会保留全部数值用于求中位数；不同值超过 `distinct_limit` 后改用 HyperLogLog，
+/// 此时高频值只统计溢出前已出现过的值
+pub fn profile_stream(
+    stream: RecordStream,
+    top: usize,
+    distinct_limit: usize,
+) -> Result<Vec<ColumnStats>> {
+    let RecordStream { headers, records } = stream;
+    let mut profiles: Vec<ColumnProfile> = headers.iter().map(|_| ColumnProfile::new()).collect();
+    for values in records {
+        for (profile, raw) in profiles.iter_mut().zip(values?) {
+            profile.observe(raw, distinct_limit);
+        }
+    }
+    Ok(headers
+        .into_iter()
+        .zip(profiles)
+        .map(|(name, profile)| profile.finish(name, top))
+        .collect())
+}
+
+struct ColumnProfile {
+    inference: TypeInference,
+    count: usize,
+    empty: usize,
+    counts: HashMap<String, usize>,
+    sketch: Option<HyperLogLog>,
+    /// 出现非数字的值后置为 None，不再收集
+    numbers: Option<Vec<f64>>,
+    min: Option<String>,
+    max: Option<String>,
+    max_length: usize,
+}
+
+impl ColumnProfile {
+    fn new() -> Self {
+        Self {
+            inference: TypeInference::default(),
+            count: 0,
+            empty: 0,
+            counts: HashMap::new(),
+            sketch: None,
+            numbers: Some(Vec::new()),
+            min: None,
+            max: None,
+            max_length: 0,
+        }
+    }
+
+    fn observe(&mut self, raw: String, distinct_limit: usize) {
+        self.count += 1;
+        self.max_length = self.max_length.max(raw.chars().count());
+        if raw.is_empty() {
+            self.empty += 1;
+            return;
+        }
+        self.inference.observe(&raw);
+        if let Some(numbers) = &mut self.numbers {
+            match raw.trim().parse::<f64>() {
+                Ok(n) if n.is_finite() => numbers.push(n),
+                _ => self.numbers = None,
+            }
+        }
+        if self.min.as_ref().is_none_or(|min| raw < *min) {
+            self.min = Some(raw.clone());
+        }
+        if self.max.as_ref().is_none_or(|max| raw > *max) {
+            self.max = Some(raw.clone());
+        }
+
+        if let Some(sketch) = &mut self.sketch {
+            sketch.insert(&raw);
+            if let Some(count) = self.counts.get_mut(&raw) {
+                *count += 1;
+            }
+            return;
+        }
+        *self.counts.entry(raw).or_insert(0) += 1;
+        if self.counts.len() > distinct_limit {
+            let mut sketch = HyperLogLog::new();
+            for value in self.counts.keys() {
+                sketch.insert(value);
+            }
+            self.sketch = Some(sketch);
+        }
+    }
+
+    fn finish(self, name: String, top: usize) -> ColumnStats {
+        let column_type = self.inference.column_type();
+        let numeric = matches!(column_type, ColumnType::Int | ColumnType::Float);
+        let mut numbers = self.numbers.filter(|_| numeric).unwrap_or_default();
+        numbers.sort_by(f64::total_cmp);
+
+        let (min, max) = match (numbers.first(), numbers.last()) {
+            (Some(min), Some(max)) => (Some(min.to_string()), Some(max.to_string())),
+            _ => (self.min, self.max),
+        };
+        let mean =
+            (!numbers.is_empty()).then(|| numbers.iter().sum::<f64>() / numbers.len() as f64);
+        let median = match numbers.len() {
+            0 => None,
+            n if n % 2 == 1 => Some(numbers[n / 2]),
+            n => Some((numbers[n / 2 - 1] + numbers[n / 2]) / 2.0),
+        };
+        // 样本标准差（n - 1）
+        let stddev = mean.filter(|_| numbers.len() > 1).map(|mean| {
+            let sum: f64 = numbers.iter().map(|n| (n - mean).powi(2)).sum();
+            (sum / (numbers.len() - 1) as f64).sqrt()
+        });
+
+        let (distinct, distinct_estimated) = match &self.sketch {
+            Some(sketch) => (sketch.estimate(), true),
+            None => (self.counts.len(), false),
+        };
+        let mut frequent: Vec<(String, usize)> = self.counts.into_iter().collect();
+        frequent.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        let top = frequent
+            .into_iter()
+            .take(top)
+            .map(|(value, count)| TopValue { value, count })
+            .collect();
+
+        ColumnStats {
+            name,
+            column_type,
+            count: self.count,
+            empty: self.empty,
+            distinct,
+            distinct_estimated,
+            min,
+            max,
+            mean,
+            median,
+            stddev,
+            top,
+            max_length: self.max_length,
+        }
+    }
+}
+
+/// 基数估算用的 HyperLogLog（2^14 个寄存器，标准误差约 0.8%）
+struct HyperLogLog {
+    registers: Vec<u8>,
+}
+
+const HLL_PRECISION: u32 = 14;
+
+impl HyperLogLog {
+    fn new() -> Self {
+        Self {
+            registers: vec![0; 1 << HLL_PRECISION],
+        }
+    }
+
+    fn insert(&mut self, value: &str) {
+        // 固定密钥的 SipHash，保证同一份数据多次运行结果一致
+        let hash = BuildHasherDefault::<DefaultHasher>::default().hash_one(value);
+        let index = (hash >> (64 - HLL_PRECISION)) as usize;
+        let rank = ((hash << HLL_PRECISION) | (1 << (HLL_PRECISION - 1))).leading_zeros() + 1;
+        self.registers[index] = self.registers[index].max(rank as u8);
+    }
+
+    fn estimate(&self) -> usize {
+        let m = self.registers.len() as f64;
+        let alpha = 0.7213 / (1.0 + 1.079 / m);
+        let sum: f64 = self.registers.iter().map(|&r| 2f64.powi(-(r as i32))).sum();
+        let estimate = alpha * m * m / sum;
+        let zeros = self.registers.iter().filter(|&&r| r == 0).count();
+        // 小基数时用线性计数修正
+        if estimate <= 2.5 * m && zeros > 0 {
+            (m * (m / zeros as f64).ln()).round() as usize
+        } else {
+            estimate.round() as usize
+        }
+    }
+}
+
+const TABLE_CELL_WIDTH: usize = 24;
+
+fn write_table(output: &mut dyn Write, stats: &[ColumnStats]) -> Result<()> {
+    let headers = [
+        "column", "type", "count", "empty", "distinct", "min", "max", "mean", "median", "stddev",
+        "max_len", "top",
+    ];
+    let rows: Vec<Vec<String>> = stats
+        .iter()
+        .map(|s| {
+            let distinct = if s.distinct_estimated {
+                format!("~{}", s.distinct)
+            } else {
+                s.distinct.to_string()
+            };
+            let top = s
+                .top
+                .iter()
+                .map(|t| format!("{} ({})", truncate_cell(&t.value, 16), t.count))
+                .collect::<Vec<_>>()
+                .join(", ");
+            vec![
+                s.name.clone(),
+                s.column_type.to_string(),
+                s.count.to_string(),
+                s.empty.to_string(),
+                distinct,
+                truncate_cell(s.min.as_deref().unwrap_or(""), TABLE_CELL_WIDTH),
+                truncate_cell(s.max.as_deref().unwrap_or(""), TABLE_CELL_WIDTH),
+                format_number(s.mean),
+                format_number(s.median),
+                format_number(s.stddev),
+                s.max_length.to_string(),
+                top,
+            ]
+        })
+        .collect();
+
+    write_text_table(output, &headers, &rows)
+}
+
+fn format_number(value: Option<f64>) -> String {
+    value.map(|v| format!("{:.2}", v)).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stream(rows: &[[&str; 3]]) -> RecordStream<'static> {
+        RecordStream::from_rows(&["id", "car", "score"], rows)
+    }
+
+    #[test]
+    fn test_profile_columns() -> Result<()> {
+        let rows = [
+            ["1", "Ford", "2.5"],
+            ["2", "Audi", ""],
+            ["10", "Ford", "3.5"],
+            ["3", "Ford", "6.5"],
+        ];
+        let stats = profile_stream(stream(&rows), 1, 100)?;
+
+        let id = &stats[0];
+        assert_eq!(id.column_type, ColumnType::Int);
+        assert_eq!((id.count, id.empty, id.distinct), (4, 0, 4));
+        assert_eq!(id.min.as_deref(), Some("1"));
+        assert_eq!(id.max.as_deref(), Some("10"));
+        assert_eq!(id.median, Some(2.5));
+
+        let car = &stats[1];
+        assert_eq!(car.column_type, ColumnType::String);
+        assert_eq!(car.min.as_deref(), Some("Audi"));
+        assert_eq!(car.mean, None);
+        assert_eq!(car.top.len(), 1);
+        assert_eq!((car.top[0].value.as_str(), car.top[0].count), ("Ford", 3));
+        assert_eq!(car.max_length, 4);
+
+        let score = &stats[2];
+        assert_eq!(score.column_type, ColumnType::Float);
+        assert_eq!((score.count, score.empty), (4, 1));
+        assert_eq!(score.mean, Some(4.166666666666667));
+        assert!((score.stddev.unwrap() - 2.0816659994661326).abs() < 1e-12);
+        Ok(())
+    }
+
+    #[test]
+    fn test_distinct_switches_to_estimate() -> Result<()> {
+        let values: Vec<String> = (0..20_000).map(|i| format!("user{}", i % 10_000)).collect();
+        let rows: Vec<Vec<String>> = values
+            .into_iter()
+            .map(|v| vec![v, String::new(), String::new()])
+            .collect();
+        let headers = vec!["id".to_string(), "car".to_string(), "score".to_string()];
+        let stream = RecordStream::new(headers, rows.into_iter().map(Ok));
+        let stats = profile_stream(stream, 3, 1000)?;
+        let id = &stats[0];
+        assert!(id.distinct_estimated);
+        let error = (id.distinct as f64 - 10_000.0).abs() / 10_000.0;
+        assert!(error < 0.05, "estimate {} too far off", id.distinct);
+        assert_eq!(stats[1].column_type, ColumnType::Null);
+        Ok(())
+    }
+}
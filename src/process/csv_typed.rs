@@ -0,0 +1,145 @@
+use anyhow::{Result, anyhow};
+use csv::StringRecord;
+use indexmap::IndexMap;
+use serde::{Serialize, de::DeserializeOwned};
+
+use crate::cli::csv::{Person, RecordProfile};
+use crate::process::csv_generate::RecordStream;
+use crate::process::csv_types::Value;
+
+// 强类型转换：每行先反序列化成调用方提供的记录结构体，再按结构体的字段类型输出。
+// 下游 crate 可以用自己的结构体调用 `convert_typed::<T>`，CLI 通过 --profile 使用内置的结构体。
+
+/// 把每一行反序列化成 `T`，列与字段按表头名称对应（遵循 serde 的 rename 等属性）。
+/// 错误信息格式为 `parse output record:{} column:{} ...`，序号是该行在数据流中的位置（从 1 开始）；
+/// 数据流经过 --where、--dedupe-by 或 --sort-by 处理后，它不等于 CSV 文件中的行号
+pub fn typed_records<'a, T: DeserializeOwned + 'a>(
+    stream: RecordStream<'a>,
+) -> impl Iterator<Item = Result<T>> + 'a {
+    let RecordStream { headers, records } = stream;
+    let header_record = StringRecord::from(headers.clone());
+    records.enumerate().map(move |(i, values)| {
+        let record = StringRecord::from(values?);
+        record
+            .deserialize::<T>(Some(&header_record))
+            .map_err(|err| {
+                let record_number = i + 1;
+                let csv::ErrorKind::Deserialize { err, .. } = err.kind() else {
+                    return anyhow!("parse output record:{} {}", record_number, err);
+                };
+                match err.field().map(|f| f as usize) {
+                    Some(field) => anyhow!(
+                        "parse output record:{} column:{} cannot parse {:?}: {}",
+                        record_number,
+                        headers.get(field).map_or("", String::as_str),
+                        record.get(field).unwrap_or_default(),
+                        err.kind()
+                    ),
+                    None => anyhow!("parse output record:{} {}", record_number, err.kind()),
+                }
+            })
+    })
+}
+
+/// 把记录序列化成输出用的行，字段顺序与结构体定义一致；`T` 必须序列化成对象
+pub fn to_typed_row<T: Serialize>(record: &T) -> Result<IndexMap<String, Value>> {
+    match Value::from(serde_json::to_value(record)?) {
+        Value::Object(row) => Ok(row),
+        other => Err(anyhow!(
+            "typed record must serialize to an object, got {}",
+            other
+        )),
+    }
+}
+
+/// 逐行反序列化成 `T` 再转换回带类型的行，供各格式的 RecordWriter 写出
+pub fn convert_typed<'a, T: DeserializeOwned + Serialize + 'a>(
+    stream: RecordStream<'a>,
+) -> impl Iterator<Item = Result<IndexMap<String, Value>>> + 'a {
+    typed_records::<T>(stream).map(|record| to_typed_row(&record?))
+}
+
+/// 逐行产出带类型的行，`convert_profile` 与 csv 转换的默认路径都产出这种迭代器
+pub type TypedRows<'a> = Box<dyn Iterator<Item = Result<IndexMap<String, Value>>> + 'a>;
+
+/// 按 --profile 选择内置的记录结构体
+pub fn convert_profile<'a>(profile: RecordProfile, stream: RecordStream<'a>) -> TypedRows<'a> {
+    match profile {
+        RecordProfile::Person => Box::new(convert_typed::<Person>(stream)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::process::csv_filter::{RowFilter, filter_stream};
+    use serde::Deserialize;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Car {
+        id: u32,
+        #[serde(rename = "Make")]
+        make: String,
+        price: Option<f64>,
+    }
+
+    fn stream(rows: &[[&str; 3]]) -> RecordStream<'static> {
+        RecordStream::from_rows(&["Make", "id", "price"], rows)
+    }
+
+    #[test]
+    fn test_typed_records() -> Result<()> {
+        let rows = [["Ford", "1", "9.5"], ["Kia", "2", ""]];
+        let cars: Vec<Car> = typed_records(stream(&rows)).collect::<Result<_>>()?;
+        assert_eq!(
+            cars[1],
+            Car {
+                id: 2,
+                make: "Kia".to_string(),
+                price: None
+            }
+        );
+
+        // 输出按结构体字段的顺序和类型
+        let rows: Vec<_> = convert_typed::<Car>(stream(&rows)).collect::<Result<_>>()?;
+        assert_eq!(
+            rows[0].keys().collect::<Vec<_>>(),
+            vec!["id", "Make", "price"]
+        );
+        assert_eq!(rows[0]["id"], Value::Int(1));
+        assert_eq!(rows[0]["price"], Value::Float(9.5));
+        assert_eq!(rows[1]["price"], Value::Null);
+        Ok(())
+    }
+
+    #[test]
+    fn test_typed_error_has_record_and_column() {
+        let rows = [["Ford", "1", "9.5"], ["Kia", "two", ""]];
+        let err = typed_records::<Car>(stream(&rows))
+            .collect::<Result<Vec<_>>>()
+            .unwrap_err();
+        let message = err.to_string();
+        assert!(
+            message.starts_with("parse output record:2 column:id cannot parse \"two\""),
+            "{}",
+            message
+        );
+    }
+
+    #[test]
+    fn test_typed_error_counts_filtered_records() -> Result<()> {
+        // 第一条被 --where 过滤掉后，出错的是输出中的第 1 条记录而不是 CSV 的第 3 行
+        let rows = [["Ford", "1", "9.5"], ["Kia", "two", ""]];
+        let filter = RowFilter::parse(r#"Make == "Kia""#, &stream(&rows).headers)?;
+        let err = typed_records::<Car>(filter_stream(stream(&rows), filter))
+            .collect::<Result<Vec<_>>>()
+            .unwrap_err();
+        assert!(
+            err.to_string()
+                .starts_with("parse output record:1 column:id"),
+            "{}",
+            err
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,445 @@
+use std::{collections::HashMap, fmt};
+
+use anyhow::{Result, anyhow};
+use indexmap::IndexMap;
+use serde::{Serialize, Serializer};
+use toml::value::Datetime;
+
+use crate::cli::csv::ColumnType;
+use crate::process::csv_generate::{RecordStream, find_column};
+
+/// 带类型的单元格值，JSON/YAML 直接序列化，TOML 通过 `to_toml_value` 转成原生类型
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    String(String),
+    Datetime(Datetime),
+    Array(Vec<Value>),
+    Object(IndexMap<String, Value>),
+}
+
+impl Serialize for Value {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Value::Null => serializer.serialize_none(),
+            Value::Bool(b) => serializer.serialize_bool(*b),
+            Value::Int(i) => serializer.serialize_i64(*i),
+            Value::Float(f) => serializer.serialize_f64(*f),
+            Value::String(s) => serializer.serialize_str(s),
+            // JSON/YAML 没有日期类型，按 ISO 8601 文本输出
+            Value::Datetime(dt) => serializer.collect_str(dt),
+            Value::Array(items) => items.serialize(serializer),
+            Value::Object(map) => map.serialize(serializer),
+        }
+    }
+}
+
+/// 单元格的文本形式，写回 CSV 等纯文本格式时使用；null 输出为空字符串
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Null => Ok(()),
+            Value::Bool(b) => write!(f, "{}", b),
+            Value::Int(i) => write!(f, "{}", i),
+            Value::Float(v) => write!(f, "{}", v),
+            Value::String(s) => f.write_str(s),
+            Value::Datetime(dt) => write!(f, "{}", dt),
+            // 嵌套值在纯文本格式中以 JSON 文本表示
+            Value::Array(_) | Value::Object(_) => {
+                let text = serde_json::to_string(self).map_err(|_| fmt::Error)?;
+                f.write_str(&text)
+            }
+        }
+    }
+}
+
+/// 强类型记录经 serde_json 序列化后转回单元格值；超出 i64 范围的整数按浮点数处理
+impl From<serde_json::Value> for Value {
+    fn from(value: serde_json::Value) -> Self {
+        match value {
+            serde_json::Value::Null => Value::Null,
+            serde_json::Value::Bool(b) => Value::Bool(b),
+            serde_json::Value::Number(n) => match n.as_i64() {
+                Some(i) => Value::Int(i),
+                None => Value::Float(n.as_f64().unwrap_or(f64::NAN)),
+            },
+            serde_json::Value::String(s) => Value::String(s),
+            serde_json::Value::Array(items) => {
+                Value::Array(items.into_iter().map(Value::from).collect())
+            }
+            serde_json::Value::Object(map) => {
+                Value::Object(map.into_iter().map(|(k, v)| (k, Value::from(v))).collect())
+            }
+        }
+    }
+}
+
+impl Value {
+    /// 转成 TOML 值；TOML 没有 null，返回 None 由调用方跳过该字段。
+    /// 数组中的 null 写成空字符串占位，以免后面元素的下标前移
+    pub fn to_toml_value(&self) -> Option<toml::Value> {
+        match self {
+            Value::Null => None,
+            Value::Bool(b) => Some(toml::Value::Boolean(*b)),
+            Value::Int(i) => Some(toml::Value::Integer(*i)),
+            Value::Float(f) => Some(toml::Value::Float(*f)),
+            Value::String(s) => Some(toml::Value::String(s.clone())),
+            Value::Datetime(dt) => Some(toml::Value::Datetime(*dt)),
+            Value::Array(items) => Some(toml::Value::Array(
+                items
+                    .iter()
+                    .map(|item| {
+                        item.to_toml_value()
+                            .unwrap_or_else(|| toml::Value::String(String::new()))
+                    })
+                    .collect(),
+            )),
+            Value::Object(map) => Some(toml::Value::Table(to_toml_table(map))),
+        }
+    }
+
+    /// 递归地把对象字段按字母顺序排序
+    pub fn sort_keys(&mut self) {
+        match self {
+            Value::Object(map) => sort_row_keys(map),
+            Value::Array(items) => items.iter_mut().for_each(Value::sort_keys),
+            _ => {}
+        }
+    }
+}
+
+pub fn sort_row_keys(row: &mut IndexMap<String, Value>) {
+    row.sort_keys();
+    row.values_mut().for_each(Value::sort_keys);
+}
+
+pub fn to_toml_table(row: &IndexMap<String, Value>) -> toml::Table {
+    row.iter()
+        .filter_map(|(k, v)| v.to_toml_value().map(|v| (k.clone(), v)))
+        .collect()
+}
+
+/// 把单元格文本按列类型转换，失败时返回 None
+pub fn parse_value(raw: &str, column_type: ColumnType) -> Option<Value> {
+    if raw.is_empty() {
+        return Some(match column_type {
+            ColumnType::String => Value::String(String::new()),
+            _ => Value::Null,
+        });
+    }
+    match column_type {
+        ColumnType::String => Some(Value::String(raw.to_string())),
+        ColumnType::Null => None,
+        ColumnType::Int => parse_int(raw).map(Value::Int),
+        ColumnType::Float => parse_float(raw).map(Value::Float),
+        ColumnType::Bool => parse_bool(raw).map(Value::Bool),
+        ColumnType::Date => parse_date(raw).map(Value::Datetime),
+        ColumnType::Datetime => parse_datetime(raw).map(Value::Datetime),
+    }
+}
+
+fn parse_int(raw: &str) -> Option<i64> {
+    let digits = raw.strip_prefix('-').unwrap_or(raw);
+    // 带前导 0 的值（邮编、编号）按字符串处理，避免丢失前导 0
+    if digits.is_empty()
+        || !digits.bytes().all(|b| b.is_ascii_digit())
+        || (digits.len() > 1 && digits.starts_with('0'))
+    {
+        return None;
+    }
+    raw.parse().ok()
+}
+
+fn parse_float(raw: &str) -> Option<f64> {
+    if let Some(i) = parse_int(raw) {
+        return Some(i as f64);
+    }
+    let digits = raw.strip_prefix('-').unwrap_or(raw);
+    // 纯数字却超出 i64 范围的值（超长编号）不当作浮点数，以免丢失精度
+    if digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let valid = digits.bytes().next().is_some_and(|b| b.is_ascii_digit())
+        && digits
+            .bytes()
+            .all(|b| b.is_ascii_digit() || matches!(b, b'.' | b'e' | b'E' | b'-' | b'+'))
+        && !(digits.starts_with('0') && digits.as_bytes().get(1).is_some_and(u8::is_ascii_digit));
+    if !valid {
+        return None;
+    }
+    raw.parse::<f64>().ok().filter(|f| f.is_finite())
+}
+
+fn parse_bool(raw: &str) -> Option<bool> {
+    if raw.eq_ignore_ascii_case("true") {
+        Some(true)
+    } else if raw.eq_ignore_ascii_case("false") {
+        Some(false)
+    } else {
+        None
+    }
+}
+
+fn parse_date(raw: &str) -> Option<Datetime> {
+    raw.parse::<Datetime>()
+        .ok()
+        .filter(|dt| dt.date.is_some() && dt.time.is_none())
+}
+
+fn parse_datetime(raw: &str) -> Option<Datetime> {
+    raw.parse::<Datetime>()
+        .ok()
+        .filter(|dt| dt.date.is_some() && dt.time.is_some())
+}
+
+/// 单列的推断状态：记录到目前为止所有非空值都满足哪些类型
+#[derive(Debug, Clone)]
+pub struct TypeInference {
+    non_empty: usize,
+    int: bool,
+    float: bool,
+    bool: bool,
+    date: bool,
+    datetime: bool,
+}
+
+impl Default for TypeInference {
+    fn default() -> Self {
+        Self {
+            non_empty: 0,
+            int: true,
+            float: true,
+            bool: true,
+            date: true,
+            datetime: true,
+        }
+    }
+}
+
+impl TypeInference {
+    pub fn observe(&mut self, raw: &str) {
+        if raw.is_empty() {
+            return;
+        }
+        self.non_empty += 1;
+        self.int = self.int && parse_int(raw).is_some();
+        self.float = self.float && parse_float(raw).is_some();
+        self.bool = self.bool && parse_bool(raw).is_some();
+        self.date = self.date && parse_date(raw).is_some();
+        self.datetime = self.datetime && parse_datetime(raw).is_some();
+    }
+
+    pub fn column_type(&self) -> ColumnType {
+        if self.non_empty == 0 {
+            ColumnType::Null
+        } else if self.int {
+            ColumnType::Int
+        } else if self.float {
+            ColumnType::Float
+        } else if self.bool {
+            ColumnType::Bool
+        } else if self.date {
+            ColumnType::Date
+        } else if self.datetime {
+            ColumnType::Datetime
+        } else {
+            ColumnType::String
+        }
+    }
+}
+
+/// 按列类型把原始字段转换成带类型的行。
+/// 推断出的类型遇到不匹配的值时退回字符串；用户显式指定的类型则直接报错。
+pub struct RowTyper {
+    headers: Vec<String>,
+    types: Vec<ColumnType>,
+    explicit: Vec<bool>,
+}
+
+impl RowTyper {
+    pub fn new(headers: Vec<String>, types: Vec<ColumnType>, explicit: Vec<bool>) -> Self {
+        Self {
+            headers,
+            types,
+            explicit,
+        }
+    }
+
+    pub fn headers(&self) -> &[String] {
+        &self.headers
+    }
+
+    pub fn types(&self) -> &[ColumnType] {
+        &self.types
+    }
+
+    /// `record_number` 是该行在输出中的序号（从 1 开始），用于错误信息；
+    /// 经过过滤、去重或排序后它不等于 CSV 文件中的行号
+    pub fn convert(&self, values: Vec<String>, record_number: usize) -> Result<Vec<Value>> {
+        values
+            .into_iter()
+            .enumerate()
+            .map(|(i, raw)| match parse_value(&raw, self.types[i]) {
+                Some(value) => Ok(value),
+                None if self.explicit[i] => Err(anyhow!(
+                    "output record:{} column:{} cannot parse {:?} as {}",
+                    record_number,
+                    self.headers[i],
+                    raw,
+                    self.types[i]
+                )),
+                None => Ok(Value::String(raw)),
+            })
+            .collect()
+    }
+
+    pub fn to_row(
+        &self,
+        values: Vec<String>,
+        record_number: usize,
+    ) -> Result<IndexMap<String, Value>> {
+        Ok(self
+            .headers
+            .iter()
+            .cloned()
+            .zip(self.convert(values, record_number)?)
+            .collect())
+    }
+}
+
+/// 读取最多 `sample_rows` 行推断各列类型，再把这些行与剩余数据重新拼成数据流返回。
+/// `overrides` 中的列使用指定类型；`infer` 为 false 时其余列都按字符串处理。
+pub fn infer_stream_types<'a>(
+    stream: RecordStream<'a>,
+    overrides: &[(String, ColumnType)],
+    infer: bool,
+    sample_rows: usize,
+) -> Result<(RowTyper, RecordStream<'a>)> {
+    let RecordStream {
+        headers,
+        mut records,
+    } = stream;
+    let overrides = resolve_overrides(&headers, overrides)?;
+
+    let mut sample = Vec::new();
+    let mut inference = vec![TypeInference::default(); headers.len()];
+    if infer {
+        for values in records.by_ref().take(sample_rows) {
+            let values = values?;
+            for (state, raw) in inference.iter_mut().zip(&values) {
+                state.observe(raw);
+            }
+            sample.push(values);
+        }
+    }
+
+    let mut types = Vec::with_capacity(headers.len());
+    let mut explicit = Vec::with_capacity(headers.len());
+    for (i, state) in inference.iter().enumerate() {
+        match overrides.get(&i) {
+            Some(t) => {
+                types.push(*t);
+                explicit.push(true);
+            }
+            None if infer => {
+                types.push(state.column_type());
+                explicit.push(false);
+            }
+            None => {
+                types.push(ColumnType::String);
+                explicit.push(false);
+            }
+        }
+    }
+
+    let typer = RowTyper::new(headers.clone(), types, explicit);
+    let records = sample.into_iter().map(Ok).chain(records);
+    Ok((typer, RecordStream::new(headers, records)))
+}
+
+fn resolve_overrides(
+    headers: &[String],
+    overrides: &[(String, ColumnType)],
+) -> Result<HashMap<usize, ColumnType>> {
+    let mut resolved = HashMap::new();
+    for (name, column_type) in overrides {
+        resolved.insert(find_column(headers, name, "--types")?, *column_type);
+    }
+    Ok(resolved)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn infer(values: &[&str]) -> ColumnType {
+        let mut state = TypeInference::default();
+        values.iter().for_each(|v| state.observe(v));
+        state.column_type()
+    }
+
+    #[test]
+    fn test_infer_column_type() {
+        assert_eq!(infer(&["1", "-20", ""]), ColumnType::Int);
+        assert_eq!(infer(&["1", "2.5", "1e3"]), ColumnType::Float);
+        assert_eq!(infer(&["true", "FALSE"]), ColumnType::Bool);
+        assert_eq!(infer(&["2024-01-31", ""]), ColumnType::Date);
+        assert_eq!(
+            infer(&["2024-01-31T10:00:00Z", "2024-02-01 08:30:00"]),
+            ColumnType::Datetime
+        );
+        assert_eq!(infer(&["", ""]), ColumnType::Null);
+        // 前导 0 和超出 i64 范围的数字保持为字符串
+        assert_eq!(infer(&["00123", "1"]), ColumnType::String);
+        assert_eq!(infer(&["99999999999999999999"]), ColumnType::String);
+        assert_eq!(infer(&["2024-02-30"]), ColumnType::String);
+    }
+
+    #[test]
+    fn test_stream_types_with_overrides() -> Result<()> {
+        let headers = vec!["id".to_string(), "price".to_string(), "zip".to_string()];
+        let rows = vec![
+            Ok(vec!["1".to_string(), "3".to_string(), "123".to_string()]),
+            Ok(vec!["2".to_string(), "4".to_string(), "abc".to_string()]),
+        ];
+        let stream = RecordStream::new(headers, rows.into_iter());
+        let overrides = vec![("price".to_string(), ColumnType::Float)];
+        let (typer, stream) = infer_stream_types(stream, &overrides, true, 1)?;
+        assert_eq!(
+            typer.types(),
+            &[ColumnType::Int, ColumnType::Float, ColumnType::Int]
+        );
+
+        let rows: Vec<Vec<String>> = stream.records.collect::<Result<_>>()?;
+        assert_eq!(rows.len(), 2);
+        let row = typer.to_row(rows[1].clone(), 3)?;
+        assert_eq!(row["price"], Value::Float(4.0));
+        // 采样之外的不匹配值退回字符串
+        assert_eq!(row["zip"], Value::String("abc".into()));
+
+        let bad = vec!["1".to_string(), "x".to_string(), "1".to_string()];
+        assert!(typer.to_row(bad, 4).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_unknown_override_column() {
+        let stream = RecordStream::new(vec!["id".to_string()], std::iter::empty());
+        let overrides = vec![("missing".to_string(), ColumnType::Int)];
+        assert!(infer_stream_types(stream, &overrides, true, 10).is_err());
+    }
+
+    #[test]
+    fn test_toml_array_keeps_null_positions() {
+        let tags = Value::Array(vec![Value::Null, Value::String("x".to_string())]);
+        let expected = toml::Value::Array(vec![
+            toml::Value::String(String::new()),
+            toml::Value::String("x".to_string()),
+        ]);
+        assert_eq!(tags.to_toml_value(), Some(expected));
+        assert_eq!(Value::Null.to_toml_value(), None);
+    }
+}
@@ -0,0 +1,285 @@
+use anyhow::{Context, Result, bail};
+use regex::Regex;
+use std::{collections::HashMap, fmt};
+
+use crate::cli::csv::{ColumnType, ValidateOpts};
+use crate::process::csv_generate::{RecordStream, open_csv_input};
+use crate::process::csv_schema::{ColumnSchema, Schema};
+use crate::process::csv_types::{Value, parse_value};
+use crate::utils::get_writer;
+
+/// 一个单元格违反的约束，`row` 是 CSV 中的行号（与 `parse csv row:{}` 一致）
+#[derive(Debug, Clone, PartialEq)]
+pub struct ValidationError {
+    pub row: usize,
+    pub column: String,
+    pub message: String,
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "parse csv row:{} column:{} {}",
+            self.row, self.column, self.message
+        )
+    }
+}
+
+pub fn process_validate(opts: &ValidateOpts) -> Result<()> {
+    let schema = Schema::load(&opts.schema)?;
+    let stream = open_csv_input(&opts.input, &opts.read, None)?;
+    let mut validator = Validator::new(&schema, &stream.headers)?;
+    let first_row = if opts.read.header { 2 } else { 1 };
+
+    let mut rejects = match &opts.rejects {
+        Some(path) => {
+            let output =
+                get_writer(path).with_context(|| format!("create rejects file:{} error", path))?;
+            let mut writer = csv::Writer::from_writer(output);
+            let mut header = stream.headers.clone();
+            header.push("_errors".to_string());
+            writer.write_record(&header)?;
+            Some(writer)
+        }
+        None => None,
+    };
+    let (mut valid, mut rejected) = (0, 0);
+    for (i, values) in stream.records.enumerate() {
+        let values = values?;
+        let errors = validator.check(&values, first_row + i);
+        if errors.is_empty() {
+            valid += 1;
+            continue;
+        }
+        // 没有指定 --rejects 时遇到第一行不合法的数据就停止
+        let Some(writer) = rejects.as_mut() else {
+            bail!("{}", errors[0]);
+        };
+        rejected += 1;
+        let messages: Vec<String> = errors
+            .iter()
+            .map(|e| format!("column:{} {}", e.column, e.message))
+            .collect();
+        let mut record = values;
+        record.push(messages.join("; "));
+        writer.write_record(&record)?;
+    }
+    if let Some(mut writer) = rejects {
+        writer.flush().context("write rejects file error")?;
+    }
+    eprintln!("{} rows valid, {} rows rejected", valid, rejected);
+    Ok(())
+}
+
+/// 按 schema 逐行检查数据；unique 约束需要记住已出现的值，所以检查是有状态的
+pub struct Validator {
+    rules: Vec<ColumnRule>,
+}
+
+struct ColumnRule {
+    index: usize,
+    schema: ColumnSchema,
+    regex: Option<Regex>,
+    /// unique 列中已出现的值及其所在行
+    seen: HashMap<String, usize>,
+}
+
+impl Validator {
+    /// schema 中的 required 列在表头里找不到时报错；其余缺失的列跳过，表头中多出的列不检查
+    pub fn new(schema: &Schema, headers: &[String]) -> Result<Self> {
+        let mut rules = Vec::new();
+        for column in &schema.columns {
+            let Some(index) = headers.iter().position(|h| *h == column.name) else {
+                if column.required {
+                    bail!(
+                        "required column {} is missing, available columns: {}",
+                        column.name,
+                        headers.join(", ")
+                    );
+                }
+                continue;
+            };
+            let regex = column
+                .regex
+                .as_deref()
+                .map(Regex::new)
+                .transpose()
+                .with_context(|| format!("invalid regex for column {}", column.name))?;
+            rules.push(ColumnRule {
+                index,
+                schema: column.clone(),
+                regex,
+                seen: HashMap::new(),
+            });
+        }
+        Ok(Self { rules })
+    }
+
+    /// 返回这一行违反的全部约束；整行合法时才记录 unique 列的值
+    pub fn check(&mut self, values: &[String], row: usize) -> Vec<ValidationError> {
+        let mut errors = Vec::new();
+        for rule in &self.rules {
+            if let Some(message) = rule.check(&values[rule.index]) {
+                errors.push(ValidationError {
+                    row,
+                    column: rule.schema.name.clone(),
+                    message,
+                });
+            }
+        }
+        if errors.is_empty() {
+            for rule in self.rules.iter_mut().filter(|r| r.schema.unique) {
+                let raw = &values[rule.index];
+                if !raw.is_empty() {
+                    rule.seen.insert(raw.clone(), row);
+                }
+            }
+        }
+        errors
+    }
+}
+
+impl ColumnRule {
+    fn check(&self, raw: &str) -> Option<String> {
+        let schema = &self.schema;
+        if raw.is_empty() {
+            return schema
+                .required
+                .then(|| "required value is missing".to_string());
+        }
+        let typed = match schema.column_type {
+            Some(column_type) => match parse_value(raw, column_type) {
+                Some(value) => Some(value),
+                None => return Some(format!("cannot parse {:?} as {}", raw, column_type)),
+            },
+            None => None,
+        };
+        if let Some(regex) = &self.regex
+            && !regex.is_match(raw)
+        {
+            return Some(format!("{:?} does not match regex {}", raw, regex));
+        }
+        if let Some(allowed) = &schema.allowed
+            && !allowed.iter().any(|v| v == raw)
+        {
+            return Some(format!("{:?} is not one of: {}", raw, allowed.join(", ")));
+        }
+        if schema.min.is_some() || schema.max.is_some() {
+            let number = match typed {
+                Some(Value::Int(i)) => Some(i as f64),
+                Some(Value::Float(f)) => Some(f),
+                _ if matches!(schema.column_type, None | Some(ColumnType::String)) => {
+                    raw.trim().parse::<f64>().ok()
+                }
+                _ => None,
+            };
+            let Some(number) = number else {
+                return Some(format!("{:?} is not a number", raw));
+            };
+            if let Some(min) = schema.min.filter(|min| number < *min) {
+                return Some(format!("{} is less than min {}", raw, min));
+            }
+            if let Some(max) = schema.max.filter(|max| number > *max) {
+                return Some(format!("{} is greater than max {}", raw, max));
+            }
+        }
+        let length = raw.chars().count();
+        if let Some(min_length) = schema.min_length.filter(|min| length < *min) {
+            return Some(format!(
+                "{:?} is shorter than {} characters",
+                raw, min_length
+            ));
+        }
+        if let Some(max_length) = schema.max_length.filter(|max| length > *max) {
+            return Some(format!(
+                "{:?} is longer than {} characters",
+                raw, max_length
+            ));
+        }
+        if let Some(first) = self.seen.get(raw) {
+            return Some(format!(
+                "duplicate value {:?}, first seen at row {}",
+                raw, first
+            ));
+        }
+        None
+    }
+}
+
+/// 检查整个数据流，返回所有不合法的单元格；`first_row` 是第一条数据在 CSV 中的行号
+pub fn validate_stream(
+    stream: RecordStream,
+    schema: &Schema,
+    first_row: usize,
+) -> Result<Vec<ValidationError>> {
+    let mut validator = Validator::new(schema, &stream.headers)?;
+    let mut errors = Vec::new();
+    for (i, values) in stream.records.enumerate() {
+        errors.extend(validator.check(&values?, first_row + i));
+    }
+    Ok(errors)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stream(rows: &[[&str; 3]]) -> RecordStream<'static> {
+        RecordStream::from_rows(&["id", "email", "gender"], rows)
+    }
+
+    fn schema() -> Result<Schema> {
+        Ok(toml::from_str(
+            r#"
+            [[columns]]
+            name = "id"
+            type = "int"
+            required = true
+            unique = true
+            min = 1
+            max = 100
+
+            [[columns]]
+            name = "email"
+            regex = "^[^@]+@[^@]+$"
+
+            [[columns]]
+            name = "gender"
+            enum = ["Male", "Female"]
+            "#,
+        )?)
+    }
+
+    #[test]
+    fn test_validate_rows() -> Result<()> {
+        let rows = [
+            ["1", "a@x.com", "Male"],
+            ["x", "b@x.com", "Female"],
+            ["1", "c@x.com", ""],
+            ["", "d@x", "Other"],
+            ["200", "", "Male"],
+        ];
+        let errors = validate_stream(stream(&rows), &schema()?, 2)?;
+        let messages: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+        assert_eq!(
+            messages,
+            vec![
+                "parse csv row:3 column:id cannot parse \"x\" as int",
+                "parse csv row:4 column:id duplicate value \"1\", first seen at row 2",
+                "parse csv row:5 column:id required value is missing",
+                "parse csv row:5 column:gender \"Other\" is not one of: Male, Female",
+                "parse csv row:6 column:id 200 is greater than max 100",
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_missing_required_column() -> Result<()> {
+        let headers = vec!["email".to_string()];
+        let err = Validator::new(&schema()?, &headers).err().unwrap();
+        assert!(err.to_string().contains("required column id is missing"));
+        Ok(())
+    }
+}
@@ -0,0 +1,656 @@
+use std::{collections::HashMap, io::Write};
+
+use indexmap::IndexMap;
+
+use anyhow::{Context, Result};
+
+use crate::cli::csv::{OutputFormat, TextEncoding};
+use crate::process::csv_encoding::encoding_label;
+use crate::process::csv_types::{Value, to_toml_table};
+
+// 增量写出记录：每条记录到达时立即序列化写出，内存占用与输入大小无关
+
+pub trait RecordWriter {
+    fn write_record(&mut self, record: &IndexMap<String, Value>) -> Result<()>;
+    /// 写出收尾内容（例如 JSON 的 `]`）并刷新底层 writer
+    fn finish(&mut self) -> Result<()>;
+}
+
+/// 各输出格式需要的附加参数
+#[derive(Debug, Clone)]
+pub struct WriterOptions {
+    pub toml_root_key: String,
+    pub xml_root: String,
+    pub xml_row: String,
+    /// 输出编码，写入 XML 声明；None 表示 UTF-8
+    pub encoding: Option<TextEncoding>,
+}
+
+impl Default for WriterOptions {
+    fn default() -> Self {
+        Self {
+            toml_root_key: "items".to_string(),
+            xml_root: "rows".to_string(),
+            xml_row: "row".to_string(),
+            encoding: None,
+        }
+    }
+}
+
+pub fn new_record_writer<'a, W: Write + 'a>(
+    format: OutputFormat,
+    writer: W,
+    options: &WriterOptions,
+) -> Box<dyn RecordWriter + 'a> {
+    match format {
+        OutputFormat::Json => Box::new(JsonArrayWriter::new(writer)),
+        OutputFormat::Jsonl => Box::new(JsonLinesWriter::new(writer)),
+        OutputFormat::Yaml => Box::new(YamlSeqWriter::new(writer)),
+        OutputFormat::YamlStream => Box::new(YamlStreamWriter::new(writer)),
+        OutputFormat::Toml => Box::new(TomlTableWriter::new(writer, &options.toml_root_key)),
+        OutputFormat::Csv => Box::new(CsvRecordWriter::new(writer)),
+        OutputFormat::Markdown => Box::new(MarkdownTableWriter::new(writer)),
+        OutputFormat::Html => Box::new(HtmlTableWriter::new(writer)),
+        OutputFormat::Xml => Box::new(XmlWriter::new(
+            writer,
+            &options.xml_root,
+            &options.xml_row,
+            encoding_label(options.encoding),
+        )),
+    }
+}
+
+/// 输出与 `serde_json::to_string_pretty(&Vec<_>)` 相同格式的 JSON 数组
+pub struct JsonArrayWriter<W: Write> {
+    writer: W,
+    count: usize,
+}
+
+impl<W: Write> JsonArrayWriter<W> {
+    pub fn new(writer: W) -> Self {
+        Self { writer, count: 0 }
+    }
+}
+
+impl<W: Write> RecordWriter for JsonArrayWriter<W> {
+    fn write_record(&mut self, record: &IndexMap<String, Value>) -> Result<()> {
+        let content = serde_json::to_string_pretty(record)?;
+        let sep = if self.count == 0 { "[\n" } else { ",\n" };
+        self.writer.write_all(sep.as_bytes())?;
+        write_indented(&mut self.writer, &content, "  ", "  ")?;
+        self.count += 1;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        let tail = if self.count == 0 { "[]" } else { "\n]" };
+        self.writer.write_all(tail.as_bytes())?;
+        self.writer.flush().context("flush json output error")
+    }
+}
+
+/// 每条记录作为 YAML 序列中的一个元素（`- key: value`）写出
+pub struct YamlSeqWriter<W: Write> {
+    writer: W,
+    count: usize,
+}
+
+impl<W: Write> YamlSeqWriter<W> {
+    pub fn new(writer: W) -> Self {
+        Self { writer, count: 0 }
+    }
+}
+
+impl<W: Write> RecordWriter for YamlSeqWriter<W> {
+    fn write_record(&mut self, record: &IndexMap<String, Value>) -> Result<()> {
+        let content = serde_yaml::to_string(record)?;
+        write_indented(&mut self.writer, content.trim_end(), "- ", "  ")?;
+        self.writer.write_all(b"\n")?;
+        self.count += 1;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        if self.count == 0 {
+            self.writer.write_all(b"[]\n")?;
+        }
+        self.writer.flush().context("flush yaml output error")
+    }
+}
+
+/// 每条记录写成一个 `[[root_key]]` 表，多个表拼接起来就是合法的 TOML 表数组
+pub struct TomlTableWriter<W: Write> {
+    writer: W,
+    root_key: String,
+    count: usize,
+}
+
+impl<W: Write> TomlTableWriter<W> {
+    pub fn new(writer: W, root_key: &str) -> Self {
+        Self {
+            writer,
+            root_key: root_key.to_string(),
+            count: 0,
+        }
+    }
+}
+
+impl<W: Write> RecordWriter for TomlTableWriter<W> {
+    fn write_record(&mut self, record: &IndexMap<String, Value>) -> Result<()> {
+        // 包一层 root key 再序列化，嵌套表的名字才会带上 `root_key.` 前缀
+        let mut root = HashMap::new();
+        root.insert(self.root_key.as_str(), [to_toml_table(record)]);
+        let content = toml::to_string_pretty(&root)?;
+        if self.count > 0 {
+            self.writer.write_all(b"\n")?;
+        }
+        self.writer.write_all(content.as_bytes())?;
+        self.count += 1;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        if self.count == 0 {
+            let mut root = HashMap::new();
+            root.insert(self.root_key.as_str(), Vec::<toml::Table>::new());
+            self.writer
+                .write_all(toml::to_string_pretty(&root)?.as_bytes())?;
+        }
+        self.writer.flush().context("flush toml output error")
+    }
+}
+
+/// 写回 CSV，第一条记录的字段名作为表头
+pub struct CsvRecordWriter<W: Write> {
+    writer: csv::Writer<W>,
+    header_written: bool,
+}
+
+impl<W: Write> CsvRecordWriter<W> {
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer: csv::Writer::from_writer(writer),
+            header_written: false,
+        }
+    }
+}
+
+impl<W: Write> RecordWriter for CsvRecordWriter<W> {
+    fn write_record(&mut self, record: &IndexMap<String, Value>) -> Result<()> {
+        if !self.header_written {
+            self.writer.write_record(record.keys())?;
+            self.header_written = true;
+        }
+        self.writer
+            .write_record(record.values().map(|v| v.to_string()))?;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        self.writer.flush().context("flush csv output error")
+    }
+}
+
+/// JSON Lines：每行一个紧凑的 JSON 对象
+pub struct JsonLinesWriter<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> JsonLinesWriter<W> {
+    pub fn new(writer: W) -> Self {
+        Self { writer }
+    }
+}
+
+impl<W: Write> RecordWriter for JsonLinesWriter<W> {
+    fn write_record(&mut self, record: &IndexMap<String, Value>) -> Result<()> {
+        serde_json::to_writer(&mut self.writer, record)?;
+        self.writer.write_all(b"\n")?;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        self.writer.flush().context("flush jsonl output error")
+    }
+}
+
+/// 多文档 YAML：每条记录是一个以 `---` 开头的独立文档，便于逐条流式处理
+pub struct YamlStreamWriter<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> YamlStreamWriter<W> {
+    pub fn new(writer: W) -> Self {
+        Self { writer }
+    }
+}
+
+impl<W: Write> RecordWriter for YamlStreamWriter<W> {
+    fn write_record(&mut self, record: &IndexMap<String, Value>) -> Result<()> {
+        // 先序列化再写出，写入错误（如管道关闭）保留为 io::Error
+        let content = serde_yaml::to_string(record)?;
+        self.writer.write_all(b"---\n")?;
+        self.writer.write_all(content.as_bytes())?;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        self.writer.flush().context("flush yaml output error")
+    }
+}
+
+/// GitHub 风格的 Markdown 表格，第一条记录的字段名作为表头
+pub struct MarkdownTableWriter<W: Write> {
+    writer: W,
+    header_written: bool,
+}
+
+impl<W: Write> MarkdownTableWriter<W> {
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer,
+            header_written: false,
+        }
+    }
+
+    fn write_line<'s>(&mut self, cells: impl Iterator<Item = &'s str>) -> Result<()> {
+        self.writer.write_all(b"|")?;
+        for cell in cells {
+            write!(self.writer, " {} |", escape_markdown(cell))?;
+        }
+        self.writer.write_all(b"\n")?;
+        Ok(())
+    }
+}
+
+impl<W: Write> RecordWriter for MarkdownTableWriter<W> {
+    fn write_record(&mut self, record: &IndexMap<String, Value>) -> Result<()> {
+        if !self.header_written {
+            self.write_line(record.keys().map(String::as_str))?;
+            self.write_line(record.keys().map(|_| "---"))?;
+            self.header_written = true;
+        }
+        let cells: Vec<String> = record.values().map(|v| v.to_string()).collect();
+        self.write_line(cells.iter().map(String::as_str))
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        self.writer.flush().context("flush markdown output error")
+    }
+}
+
+fn escape_markdown(cell: &str) -> String {
+    cell.replace('\\', "\\\\")
+        .replace('|', "\\|")
+        .replace("\r\n", "<br>")
+        .replace('\n', "<br>")
+}
+
+/// HTML `<table>`，第一条记录的字段名作为 `<thead>`
+pub struct HtmlTableWriter<W: Write> {
+    writer: W,
+    header_written: bool,
+}
+
+impl<W: Write> HtmlTableWriter<W> {
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer,
+            header_written: false,
+        }
+    }
+}
+
+impl<W: Write> RecordWriter for HtmlTableWriter<W> {
+    fn write_record(&mut self, record: &IndexMap<String, Value>) -> Result<()> {
+        if !self.header_written {
+            self.writer.write_all(b"<table>\n  <thead>\n    <tr>")?;
+            for key in record.keys() {
+                write!(self.writer, "<th>{}</th>", escape_xml(key))?;
+            }
+            self.writer.write_all(b"</tr>\n  </thead>\n  <tbody>\n")?;
+            self.header_written = true;
+        }
+        self.writer.write_all(b"    <tr>")?;
+        for value in record.values() {
+            write!(self.writer, "<td>{}</td>", escape_xml(&value.to_string()))?;
+        }
+        self.writer.write_all(b"</tr>\n")?;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        if self.header_written {
+            self.writer.write_all(b"  </tbody>\n</table>\n")?;
+        } else {
+            self.writer.write_all(b"<table></table>\n")?;
+        }
+        self.writer.flush().context("flush html output error")
+    }
+}
+
+/// XML：`<root><row><field>value</field></row></root>`，
+/// 嵌套对象输出为子元素，数组的每个元素输出为 `<item>`
+pub struct XmlWriter<W: Write> {
+    writer: W,
+    root: String,
+    row: String,
+    encoding: &'static str,
+    started: bool,
+}
+
+impl<W: Write> XmlWriter<W> {
+    /// `encoding` 是写入 XML 声明的编码名，需与实际输出的字节编码一致
+    pub fn new(writer: W, root: &str, row: &str, encoding: &'static str) -> Self {
+        Self {
+            writer,
+            root: xml_name(root),
+            row: xml_name(row),
+            encoding,
+            started: false,
+        }
+    }
+
+    fn start(&mut self) -> Result<()> {
+        if !self.started {
+            writeln!(
+                self.writer,
+                "<?xml version=\"1.0\" encoding=\"{}\"?>",
+                self.encoding
+            )?;
+            writeln!(self.writer, "<{}>", self.root)?;
+            self.started = true;
+        }
+        Ok(())
+    }
+
+    fn write_element(&mut self, name: &str, value: &Value, depth: usize) -> Result<()> {
+        let indent = "  ".repeat(depth);
+        let name = xml_name(name);
+        match value {
+            Value::Null => writeln!(self.writer, "{}<{}/>", indent, name)?,
+            Value::Object(map) => {
+                writeln!(self.writer, "{}<{}>", indent, name)?;
+                for (key, child) in map {
+                    self.write_element(key, child, depth + 1)?;
+                }
+                writeln!(self.writer, "{}</{}>", indent, name)?;
+            }
+            Value::Array(items) => {
+                writeln!(self.writer, "{}<{}>", indent, name)?;
+                for child in items {
+                    self.write_element("item", child, depth + 1)?;
+                }
+                writeln!(self.writer, "{}</{}>", indent, name)?;
+            }
+            scalar => writeln!(
+                self.writer,
+                "{}<{}>{}</{}>",
+                indent,
+                name,
+                escape_xml(&scalar.to_string()),
+                name
+            )?,
+        }
+        Ok(())
+    }
+}
+
+impl<W: Write> RecordWriter for XmlWriter<W> {
+    fn write_record(&mut self, record: &IndexMap<String, Value>) -> Result<()> {
+        self.start()?;
+        let row = self.row.clone();
+        writeln!(self.writer, "  <{}>", row)?;
+        for (key, value) in record {
+            self.write_element(key, value, 2)?;
+        }
+        writeln!(self.writer, "  </{}>", row)?;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        self.start()?;
+        writeln!(self.writer, "</{}>", self.root)?;
+        self.writer.flush().context("flush xml output error")
+    }
+}
+
+fn escape_xml(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// 把任意字段名转换成合法的 XML 元素名：非法字符替换为 `_`，不能作为开头的字符前补 `_`
+fn xml_name(name: &str) -> String {
+    let mut result: String = name
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || matches!(c, '_' | '-' | '.') {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    let valid_start = result
+        .chars()
+        .next()
+        .is_some_and(|c| c.is_alphabetic() || c == '_');
+    if !valid_start || result.to_lowercase().starts_with("xml") {
+        result.insert(0, '_');
+    }
+    result
+}
+
+fn write_indented<W: Write>(writer: &mut W, content: &str, first: &str, rest: &str) -> Result<()> {
+    for (i, line) in content.lines().enumerate() {
+        if i > 0 {
+            writer.write_all(b"\n")?;
+        }
+        let prefix = if i == 0 { first } else { rest };
+        writer.write_all(prefix.as_bytes())?;
+        writer.write_all(line.as_bytes())?;
+    }
+    Ok(())
+}
+
+/// 写出按列对齐的纯文本表格（表头、分隔线、数据行），用于 stats、diff 等面向人阅读的输出
+pub fn write_text_table(
+    output: &mut dyn Write,
+    headers: &[&str],
+    rows: &[Vec<String>],
+) -> Result<()> {
+    let mut widths: Vec<usize> = headers.iter().map(|h| h.chars().count()).collect();
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+    let headers: Vec<String> = headers.iter().map(|h| h.to_string()).collect();
+    write_text_table_row(output, &headers, &widths)?;
+    let separator: Vec<String> = widths.iter().map(|&w| "-".repeat(w)).collect();
+    write_text_table_row(output, &separator, &widths)?;
+    for row in rows {
+        write_text_table_row(output, row, &widths)?;
+    }
+    Ok(())
+}
+
+fn write_text_table_row(output: &mut dyn Write, cells: &[String], widths: &[usize]) -> Result<()> {
+    let line = cells
+        .iter()
+        .zip(widths)
+        .map(|(cell, &width)| {
+            let padding = width.saturating_sub(cell.chars().count());
+            format!("{}{}", cell, " ".repeat(padding))
+        })
+        .collect::<Vec<_>>()
+        .join("  ");
+    writeln!(output, "{}", line.trim_end())?;
+    Ok(())
+}
+
+/// 超过 `width` 个字符时截断并以 `…` 结尾
+pub fn truncate_cell(value: &str, width: usize) -> String {
+    if value.chars().count() <= width {
+        value.to_string()
+    } else {
+        let mut truncated: String = value.chars().take(width - 1).collect();
+        truncated.push('…');
+        truncated
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+
+    fn sample_records() -> Vec<IndexMap<String, Value>> {
+        (1..=3)
+            .map(|i| {
+                let mut row = IndexMap::new();
+                row.insert("id".to_string(), Value::Int(i));
+                row.insert(
+                    "name".to_string(),
+                    Value::String(format!("name \"{}\"\nline", i)),
+                );
+                let born = format!("2020-01-0{}", i).parse().unwrap();
+                row.insert("born".to_string(), Value::Datetime(born));
+                row.insert("score".to_string(), Value::Null);
+                row
+            })
+            .collect()
+    }
+
+    fn write_all(format: OutputFormat, records: &[IndexMap<String, Value>]) -> Result<String> {
+        let mut buf = Vec::new();
+        let mut writer = new_record_writer(format, &mut buf, &WriterOptions::default());
+        for record in records {
+            writer.write_record(record)?;
+        }
+        writer.finish()?;
+        drop(writer);
+        Ok(String::from_utf8(buf)?)
+    }
+
+    #[test]
+    fn test_json_writer_matches_pretty_vec() -> Result<()> {
+        let records = sample_records();
+        let content = write_all(OutputFormat::Json, &records)?;
+        assert_eq!(content, serde_json::to_string_pretty(&records)?);
+        // 字段顺序与记录中的插入顺序一致
+        let id_pos = content.find("\"id\"").unwrap();
+        let name_pos = content.find("\"name\"").unwrap();
+        assert!(id_pos < name_pos);
+        assert_eq!(write_all(OutputFormat::Json, &[])?, "[]");
+        Ok(())
+    }
+
+    #[test]
+    fn test_yaml_writer_roundtrip() -> Result<()> {
+        let records = sample_records();
+        let content = write_all(OutputFormat::Yaml, &records)?;
+        let parsed: serde_yaml::Value = serde_yaml::from_str(&content)?;
+        assert_eq!(parsed, serde_yaml::to_value(&records)?);
+        let empty: Vec<IndexMap<String, String>> =
+            serde_yaml::from_str(&write_all(OutputFormat::Yaml, &[])?)?;
+        assert!(empty.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_toml_writer_roundtrip() -> Result<()> {
+        let records = sample_records();
+        let content = write_all(OutputFormat::Toml, &records)?;
+        let parsed: toml::Table = toml::from_str(&content)?;
+        let items = parsed["items"].as_array().unwrap();
+        assert_eq!(items.len(), 3);
+        assert_eq!(items[0]["id"], toml::Value::Integer(1));
+        assert!(items[0]["born"].is_datetime());
+        // TOML 没有 null，空值字段被跳过
+        assert!(items[0].get("score").is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_csv_writer() -> Result<()> {
+        let content = write_all(OutputFormat::Csv, &sample_records()[..1])?;
+        assert_eq!(
+            content,
+            "id,name,born,score\n1,\"name \"\"1\"\"\nline\",2020-01-01,\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_line_oriented_writers() -> Result<()> {
+        let records = sample_records();
+        let content = write_all(OutputFormat::Jsonl, &records)?;
+        let lines: Vec<&str> = content.lines().collect();
+        assert_eq!(lines.len(), 3);
+        let first: serde_json::Value = serde_json::from_str(lines[0])?;
+        assert_eq!(first["id"], 1);
+
+        let content = write_all(OutputFormat::YamlStream, &records)?;
+        let docs: Vec<serde_yaml::Value> = serde_yaml::Deserializer::from_str(&content)
+            .map(serde_yaml::Value::deserialize)
+            .collect::<Result<_, _>>()?;
+        assert_eq!(docs.len(), 3);
+        Ok(())
+    }
+
+    #[test]
+    fn test_table_writers_escape_cells() -> Result<()> {
+        let mut row = IndexMap::new();
+        row.insert("a|b".to_string(), Value::String("x|y\nz".into()));
+        row.insert("html".to_string(), Value::String("<b>&</b>".into()));
+        let records = vec![row];
+
+        let content = write_all(OutputFormat::Markdown, &records)?;
+        assert_eq!(
+            content,
+            "| a\\|b | html |\n| --- | --- |\n| x\\|y<br>z | <b>&</b> |\n"
+        );
+
+        let content = write_all(OutputFormat::Html, &records)?;
+        assert!(content.contains("<th>a|b</th><th>html</th>"));
+        assert!(content.contains("<td>&lt;b&gt;&amp;&lt;/b&gt;</td>"));
+        assert!(content.ends_with("</table>\n"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_xml_writer() -> Result<()> {
+        let mut row = IndexMap::new();
+        row.insert("1st name".to_string(), Value::String("a<b".into()));
+        row.insert("tags".to_string(), Value::Array(vec![Value::Int(1)]));
+        row.insert("none".to_string(), Value::Null);
+        let content = write_all(OutputFormat::Xml, &[row])?;
+        assert_eq!(
+            content,
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<rows>\n  <row>\n    <_1st_name>a&lt;b</_1st_name>\n    <tags>\n      <item>1</item>\n    </tags>\n    <none/>\n  </row>\n</rows>\n"
+        );
+        assert!(write_all(OutputFormat::Xml, &[])?.ends_with("<rows>\n</rows>\n"));
+
+        // 声明的编码跟随 --output-encoding
+        let mut buf = Vec::new();
+        let options = WriterOptions {
+            encoding: Some(TextEncoding::Gbk),
+            ..Default::default()
+        };
+        new_record_writer(OutputFormat::Xml, &mut buf, &options).finish()?;
+        assert!(String::from_utf8(buf)?.starts_with("<?xml version=\"1.0\" encoding=\"GBK\"?>"));
+        Ok(())
+    }
+}
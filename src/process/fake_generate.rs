@@ -0,0 +1,621 @@
+use anyhow::{Context, Result, bail};
+use rand::{Rng, SeedableRng, seq::SliceRandom};
+use rand_chacha::ChaCha20Rng;
+use serde::Deserialize;
+use std::{
+    fs,
+    net::{Ipv4Addr, Ipv6Addr},
+};
+
+use crate::cli::fake::{FakeOpts, FieldKind};
+use crate::process::csv_generate::{RecordStream, write_stream};
+
+/// 生成假数据的字段定义：
+///
+/// ```toml
+/// [[fields]]
+/// name = "id"
+/// kind = "id"
+///
+/// [[fields]]
+/// name = "price"
+/// kind = "float"
+/// min = 1
+/// max = 500
+/// null_rate = 0.1
+///
+/// [[fields]]
+/// name = "signup"
+/// kind = "date"
+/// from = "2020-01-01"
+/// to = "2024-12-31"
+/// ```
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FakeSchema {
+    pub fields: Vec<FieldSpec>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FieldSpec {
+    pub name: String,
+    pub kind: FieldKind,
+    /// int/float 的取值范围（含边界）
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+    /// float 保留的小数位数
+    pub decimals: Option<usize>,
+    /// date/datetime 的日期范围，格式 YYYY-MM-DD
+    pub from: Option<String>,
+    pub to: Option<String>,
+    /// id 的起始值
+    pub start: Option<u64>,
+    /// choice 的候选值
+    #[serde(default)]
+    pub values: Vec<String>,
+    /// 生成空值的概率
+    #[serde(default)]
+    pub null_rate: f64,
+}
+
+impl FakeSchema {
+    pub fn load(path: &str) -> Result<Self> {
+        let content =
+            fs::read_to_string(path).with_context(|| format!("read schema file:{} error", path))?;
+        toml::from_str(&content).with_context(|| format!("parse fake schema:{} error", path))
+    }
+}
+
+pub fn process_fake(opts: &FakeOpts) -> Result<()> {
+    let schema = FakeSchema::load(&opts.schema)?;
+    let stream = fake_stream(&schema, opts.rows, opts.seed)?;
+    write_stream(stream, &opts.write)?;
+    Ok(())
+}
+
+/// 按字段定义逐行生成 `rows` 行数据。随机数算法固定为 ChaCha20，`seed` 相同时输出相同，
+/// 不随 rand 版本升级而变化
+pub fn fake_stream(
+    schema: &FakeSchema,
+    rows: usize,
+    seed: Option<u64>,
+) -> Result<RecordStream<'static>> {
+    if schema.fields.is_empty() {
+        bail!("fake schema has no fields");
+    }
+    for (i, field) in schema.fields.iter().enumerate() {
+        if schema.fields[..i].iter().any(|f| f.name == field.name) {
+            bail!("field {} is defined twice in fake schema", field.name);
+        }
+    }
+    let fields = schema
+        .fields
+        .iter()
+        .map(FieldGenerator::new)
+        .collect::<Result<Vec<_>>>()?;
+    let headers = schema.fields.iter().map(|f| f.name.clone()).collect();
+    let mut rng = match seed {
+        Some(seed) => ChaCha20Rng::seed_from_u64(seed),
+        None => ChaCha20Rng::from_entropy(),
+    };
+    let records = (0..rows).map(move |row| Ok(generate_row(&fields, &mut rng, row)));
+    Ok(RecordStream::new(headers, records))
+}
+
+/// 按默认参数生成一个假值，供 --mask col=fake 替换原值；rng 状态相同时结果相同
+pub fn fake_value(kind: FieldKind, rng: &mut ChaCha20Rng) -> Result<String> {
+    let spec = FieldSpec {
+        name: String::new(),
+        kind,
+        min: None,
+        max: None,
+        decimals: None,
+        from: None,
+        to: None,
+        start: None,
+        values: Vec::new(),
+        null_rate: 0.0,
+    };
+    let field = FieldGenerator::new(&spec)?;
+    Ok(generate_row(std::slice::from_ref(&field), rng, 0).swap_remove(0))
+}
+
+/// 校验过参数的字段生成器，日期范围已转换成天数
+struct FieldGenerator {
+    kind: FieldKind,
+    min: f64,
+    max: f64,
+    decimals: usize,
+    days: (i64, i64),
+    start: u64,
+    values: Vec<String>,
+    null_rate: f64,
+}
+
+impl FieldGenerator {
+    fn new(spec: &FieldSpec) -> Result<Self> {
+        let min = spec.min.unwrap_or(0.0);
+        let max = spec.max.unwrap_or(1000.0);
+        if !min.is_finite() || !max.is_finite() {
+            bail!("field {}: min and max must be finite numbers", spec.name);
+        }
+        if min > max {
+            bail!(
+                "field {}: min {} is greater than max {}",
+                spec.name,
+                min,
+                max
+            );
+        }
+        if spec.kind == FieldKind::Int && min.ceil() > max.floor() {
+            bail!("field {}: no integer between min and max", spec.name);
+        }
+        let parse = |date: &Option<String>, default: &str| {
+            let date = date.as_deref().unwrap_or(default);
+            parse_days(date)
+                .with_context(|| format!("field {}: invalid date {:?}", spec.name, date))
+        };
+        let days = (
+            parse(&spec.from, "2000-01-01")?,
+            parse(&spec.to, "2025-12-31")?,
+        );
+        if days.0 > days.1 {
+            bail!("field {}: from is later than to", spec.name);
+        }
+        if spec.kind == FieldKind::Choice && spec.values.is_empty() {
+            bail!(
+                "field {}: kind choice needs a non-empty values list",
+                spec.name
+            );
+        }
+        if !(0.0..=1.0).contains(&spec.null_rate) {
+            bail!("field {}: null_rate must be between 0 and 1", spec.name);
+        }
+        Ok(Self {
+            kind: spec.kind,
+            min,
+            max,
+            decimals: spec.decimals.unwrap_or(2),
+            days,
+            start: spec.start.unwrap_or(1),
+            values: spec.values.clone(),
+            null_rate: spec.null_rate,
+        })
+    }
+}
+
+const FIRST_NAMES: &[&str] = &[
+    "Amalle",
+    "Donal",
+    "Catherin",
+    "Agretha",
+    "Bertie",
+    "Cordelia",
+    "Dmitri",
+    "Elsie",
+    "Fergus",
+    "Gwendolyn",
+    "Hamish",
+    "Ingrid",
+    "Jasper",
+    "Katya",
+    "Lorenzo",
+    "Maren",
+    "Niall",
+    "Ottilie",
+    "Perrin",
+    "Quinn",
+    "Rosalind",
+    "Silas",
+    "Tamsin",
+    "Ulric",
+    "Vivienne",
+    "Wendel",
+    "Xanthe",
+    "Yusuf",
+    "Zelda",
+    "Ahmed",
+    "Beatriz",
+    "Chen",
+    "Dara",
+    "Emeka",
+    "Freya",
+    "Hiro",
+    "Isla",
+    "Joaquin",
+    "Leila",
+    "Mateo",
+    "Noor",
+    "Priya",
+    "Sven",
+    "Wei",
+];
+
+const LAST_NAMES: &[&str] = &[
+    "Ohm",
+    "Perrelli",
+    "von Nassau",
+    "Fennelly",
+    "Abbott",
+    "Blackwood",
+    "Castellanos",
+    "Dunmore",
+    "Eriksen",
+    "Fairweather",
+    "Gallagher",
+    "Hargreaves",
+    "Ivanova",
+    "Jovanovic",
+    "Kowalski",
+    "Lindqvist",
+    "MacAllister",
+    "Nakamura",
+    "O'Connell",
+    "Pemberton",
+    "Quintero",
+    "Rasmussen",
+    "Sutherland",
+    "Thornbury",
+    "Underhill",
+    "Valdez",
+    "Whitcombe",
+    "Yamamoto",
+    "Zimmermann",
+    "Okafor",
+    "Haddad",
+    "Moreau",
+    "Novak",
+    "Patel",
+    "Silva",
+    "Tanaka",
+    "Weber",
+];
+
+const DOMAINS: &[&str] = &[
+    "icq.com",
+    "bandcamp.com",
+    "deliciousdays.com",
+    "eepurl.com",
+    "example.com",
+    "mail.net",
+    "webnode.org",
+    "tinyurl.io",
+    "wikimedia.org",
+    "github.io",
+    "about.me",
+    "ovh.net",
+];
+
+const CITIES: &[&str] = &[
+    "Mazatenango",
+    "Mopipi",
+    "Bradashesh",
+    "Bazzāryah",
+    "Paris",
+    "Lyon",
+    "Berlin",
+    "Hamburg",
+    "Rome",
+    "Milan",
+    "Oslo",
+    "Bergen",
+    "Lisbon",
+    "Porto",
+    "Kraków",
+    "Gdańsk",
+    "Osaka",
+    "Sapporo",
+    "Chengdu",
+    "Hangzhou",
+    "Lagos",
+    "Nairobi",
+    "Lima",
+    "Cusco",
+    "Austin",
+    "Denver",
+    "Perth",
+    "Hobart",
+    "Tromsø",
+    "Valparaíso",
+    "Ljubljana",
+    "Tbilisi",
+];
+
+const CAR_MAKES: &[&str] = &[
+    "Ford",
+    "Mercedes-Benz",
+    "Mazda",
+    "Toyota",
+    "Honda",
+    "Chevrolet",
+    "Volkswagen",
+    "BMW",
+    "Audi",
+    "Nissan",
+    "Hyundai",
+    "Kia",
+    "Subaru",
+    "Volvo",
+    "Porsche",
+    "Lexus",
+    "Dodge",
+    "Jeep",
+    "Suzuki",
+    "Mitsubishi",
+    "Saab",
+    "Infiniti",
+    "Cadillac",
+    "Buick",
+    "Lamborghini",
+];
+
+const WORDS: &[&str] = &[
+    "lorem",
+    "ipsum",
+    "dolor",
+    "sit",
+    "amet",
+    "utquidolores",
+    "assumenda",
+    "rationeculpa",
+    "deserunt",
+    "velit",
+    "magnam",
+    "quia",
+    "nihil",
+    "tempora",
+    "sapiente",
+    "officia",
+];
+
+/// 权重大致与 MOCK_DATA.csv 中的分布一致
+const GENDERS: &[(&str, u32)] = &[
+    ("Male", 45),
+    ("Female", 45),
+    ("Agender", 2),
+    ("Bigender", 2),
+    ("Genderfluid", 2),
+    ("Genderqueer", 1),
+    ("Non-binary", 2),
+    ("Polygender", 1),
+];
+
+fn generate_row(fields: &[FieldGenerator], rng: &mut ChaCha20Rng, row: usize) -> Vec<String> {
+    // 同一行里的邮箱使用这一行生成的姓名，让数据看起来更真实
+    let mut first_name: Option<&str> = None;
+    let mut last_name: Option<&str> = None;
+    fields
+        .iter()
+        .map(|field| {
+            if field.null_rate > 0.0 && rng.gen_bool(field.null_rate) {
+                return String::new();
+            }
+            match field.kind {
+                FieldKind::Id => (field.start + row as u64).to_string(),
+                FieldKind::Uuid => uuid_v4(rng.r#gen()),
+                FieldKind::FirstName => {
+                    let name = pick(rng, FIRST_NAMES);
+                    first_name = Some(name);
+                    name.to_string()
+                }
+                FieldKind::LastName => {
+                    let name = pick(rng, LAST_NAMES);
+                    last_name = Some(name);
+                    name.to_string()
+                }
+                FieldKind::FullName => {
+                    format!("{} {}", pick(rng, FIRST_NAMES), pick(rng, LAST_NAMES))
+                }
+                FieldKind::Email => {
+                    let first = first_name.unwrap_or_else(|| pick(rng, FIRST_NAMES));
+                    let last = last_name.unwrap_or_else(|| pick(rng, LAST_NAMES));
+                    let user: String = format!("{}{}", &first[..1], last)
+                        .chars()
+                        .filter(char::is_ascii_alphanumeric)
+                        .collect();
+                    format!(
+                        "{}{}@{}",
+                        user.to_lowercase(),
+                        rng.gen_range(0..100),
+                        pick(rng, DOMAINS)
+                    )
+                }
+                FieldKind::Gender => GENDERS
+                    .choose_weighted(rng, |(_, weight)| *weight)
+                    .expect("weights are valid")
+                    .0
+                    .to_string(),
+                // 首字节避开 0、127 和组播/保留地址段
+                FieldKind::Ipv4 => {
+                    let first = loop {
+                        let first = rng.gen_range(1..224u8);
+                        if first != 127 {
+                            break first;
+                        }
+                    };
+                    Ipv4Addr::new(first, rng.r#gen(), rng.r#gen(), rng.r#gen()).to_string()
+                }
+                FieldKind::Ipv6 => {
+                    let bits = (0x2001_u128 << 112) | (rng.r#gen::<u128>() >> 16);
+                    Ipv6Addr::from(bits).to_string()
+                }
+                FieldKind::City => pick(rng, CITIES).to_string(),
+                FieldKind::Url => format!(
+                    "https://{}/{}/{}",
+                    pick(rng, DOMAINS),
+                    pick(rng, WORDS),
+                    pick(rng, WORDS)
+                ),
+                FieldKind::Avatar => format!(
+                    "https://robohash.org/{}{}.png?size=50x50&set=set1",
+                    pick(rng, WORDS),
+                    pick(rng, WORDS)
+                ),
+                FieldKind::Car => pick(rng, CAR_MAKES).to_string(),
+                FieldKind::Date => format_date(rng.gen_range(field.days.0..=field.days.1)),
+                FieldKind::Datetime => format!(
+                    "{}T{:02}:{:02}:{:02}",
+                    format_date(rng.gen_range(field.days.0..=field.days.1)),
+                    rng.gen_range(0..24),
+                    rng.gen_range(0..60),
+                    rng.gen_range(0..60)
+                ),
+                FieldKind::Int => rng
+                    .gen_range(field.min.ceil() as i64..=field.max.floor() as i64)
+                    .to_string(),
+                FieldKind::Float => {
+                    let value = rng.gen_range(field.min..=field.max);
+                    format!("{:.*}", field.decimals, value)
+                }
+                FieldKind::Bool => rng.gen_bool(0.5).to_string(),
+                FieldKind::Choice => field.values.choose(rng).cloned().unwrap_or_default(),
+            }
+        })
+        .collect()
+}
+
+fn pick(rng: &mut ChaCha20Rng, list: &[&'static str]) -> &'static str {
+    list.choose(rng).expect("word lists are not empty")
+}
+
+fn uuid_v4(bits: u128) -> String {
+    // 设置版本号 4 和 RFC 4122 变体位
+    let bits = (bits & !(0xf << 76) & !(0x3 << 62)) | (0x4 << 76) | (0x2 << 62);
+    let hex = format!("{:032x}", bits);
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..]
+    )
+}
+
+/// YYYY-MM-DD 转成 1970-01-01 起的天数
+fn parse_days(date: &str) -> Option<i64> {
+    let mut parts = date.splitn(3, '-');
+    let year: i64 = parts.next()?.parse().ok()?;
+    let month: i64 = parts.next()?.parse().ok()?;
+    let day: i64 = parts.next()?.parse().ok()?;
+    if !(1..=12).contains(&month) || day < 1 || day > days_in_month(year, month) {
+        return None;
+    }
+    // Howard Hinnant 的 days_from_civil 算法
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let mp = (month + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    Some(era * 146097 + doe - 719468)
+}
+
+fn format_date(days: i64) -> String {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+fn days_in_month(year: i64, month: i64) -> i64 {
+    match month {
+        2 if (year % 4 == 0 && year % 100 != 0) || year % 400 == 0 => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn schema() -> FakeSchema {
+        toml::from_str(
+            r#"
+            [[fields]]
+            name = "id"
+            kind = "id"
+            start = 10
+
+            [[fields]]
+            name = "first_name"
+            kind = "first_name"
+
+            [[fields]]
+            name = "email"
+            kind = "email"
+
+            [[fields]]
+            name = "ip"
+            kind = "ipv4"
+
+            [[fields]]
+            name = "age"
+            kind = "int"
+            min = 18
+            max = 65
+
+            [[fields]]
+            name = "day"
+            kind = "date"
+            from = "2024-02-27"
+            to = "2024-03-02"
+            "#,
+        )
+        .unwrap()
+    }
+
+    fn generate(seed: u64) -> Result<Vec<Vec<String>>> {
+        fake_stream(&schema(), 50, Some(seed))?.records.collect()
+    }
+
+    #[test]
+    fn test_fake_rows_are_reproducible_and_valid() -> Result<()> {
+        let rows = generate(42)?;
+        assert_eq!(rows, generate(42)?);
+        assert_ne!(rows, generate(7)?);
+        for (i, row) in rows.iter().enumerate() {
+            assert_eq!(row[0], (10 + i).to_string());
+            let initial = row[1][..1].to_lowercase();
+            assert!(row[2].starts_with(&initial) && row[2].contains('@'));
+            assert!(row[3].parse::<Ipv4Addr>().is_ok());
+            assert!((18..=65).contains(&row[4].parse::<i64>()?));
+            assert!(("2024-02-27"..="2024-03-02").contains(&row[5].as_str()));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_dates_and_invalid_fields() {
+        assert_eq!(parse_days("1970-01-01"), Some(0));
+        assert_eq!(format_date(parse_days("2024-02-29").unwrap()), "2024-02-29");
+        assert_eq!(parse_days("2023-02-29"), None);
+        assert!(uuid_v4(u128::MAX).starts_with("ffffffff-ffff-4fff-bfff"));
+
+        let choice: FakeSchema =
+            toml::from_str("[[fields]]\nname = \"x\"\nkind = \"choice\"").unwrap();
+        assert!(fake_stream(&choice, 1, None).is_err());
+        assert!(toml::from_str::<FakeSchema>("[[fields]]\nname = \"x\"\nkind = \"zip\"").is_err());
+
+        let nan: FakeSchema =
+            toml::from_str("[[fields]]\nname = \"x\"\nkind = \"float\"\nmin = nan").unwrap();
+        let err = fake_stream(&nan, 1, None).err().unwrap();
+        assert!(err.to_string().contains("must be finite"), "{}", err);
+
+        let twice: FakeSchema = toml::from_str(
+            "[[fields]]\nname = \"x\"\nkind = \"id\"\n[[fields]]\nname = \"x\"\nkind = \"id\"",
+        )
+        .unwrap();
+        let err = fake_stream(&twice, 1, None).err().unwrap();
+        assert!(err.to_string().contains("defined twice"), "{}", err);
+    }
+}
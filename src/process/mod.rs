@@ -1,4 +1,25 @@
 pub mod b64;
+pub mod csv_aggregate;
+pub mod csv_cat;
+pub mod csv_diff;
+pub mod csv_encoding;
+pub mod csv_filter;
 pub mod csv_generate;
+pub mod csv_join;
+pub mod csv_mask;
+pub mod csv_nested;
+pub mod csv_query;
+pub mod csv_reverse;
+pub mod csv_schema;
+pub mod csv_select;
+pub mod csv_sheet;
+pub mod csv_sort;
+pub mod csv_split;
+pub mod csv_stats;
+pub mod csv_typed;
+pub mod csv_types;
+pub mod csv_validate;
+pub mod csv_writer;
+pub mod fake_generate;
 pub mod password_generate;
 pub mod text;
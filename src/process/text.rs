@@ -46,6 +46,11 @@ impl Blake3 {
         Ok(Self::new(key))
     }
 
+    /// 用密钥对任意数据做 keyed hash，csv --mask 用它生成不可逆的假名
+    pub fn keyed_hash(&self, data: &[u8]) -> blake3::Hash {
+        blake3::keyed_hash(&self.key, data)
+    }
+
     pub fn generate_key() -> Result<HashMap<&'static str, Vec<u8>>> {
         // 使用 CSPRNG 生成更安全的随机密钥，而不是密码生成函数
         let mut key = [0u8; 32];
@@ -1,9 +1,14 @@
 use anyhow::{Ok, Result};
 use std::{
     fs,
-    io::{Read, stdin},
+    io::{BufRead, BufReader, Read, Write, stdin, stdout},
 };
 
+use crate::cli::csv::{Compression, OutputFormat};
+
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+const ZSTD_MAGIC: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd];
+
 pub fn verify_file_exists(path: &str) -> Result<String, anyhow::Error> {
     if path == "-" {
         // 如果输入是 "-", 认为它是有效的特殊值，直接原样返回
@@ -25,12 +30,9 @@ pub fn verify_file_exists(path: &str) -> Result<String, anyhow::Error> {
 }
 
 pub fn verify_format_valid(format: &str) -> Result<String, anyhow::Error> {
-    match format {
-        "json" => Ok(format.to_string()),
-        "yaml" => Ok(format.to_string()),
-        "toml" => Ok(format.to_string()),
-        _ => Err(anyhow::anyhow!("format is not supporteded")),
-    }
+    // 与 OutputFormat::from_str 共用同一张格式注册表
+    format.parse::<OutputFormat>()?;
+    Ok(format.to_string())
 }
 
 pub fn get_reader(key: &str) -> Result<Box<dyn Read>, anyhow::Error> {
@@ -42,6 +44,114 @@ pub fn get_reader(key: &str) -> Result<Box<dyn Read>, anyhow::Error> {
     Ok(reader)
 }
 
+pub fn get_writer(path: &str) -> Result<Box<dyn Write>, anyhow::Error> {
+    let writer: Box<dyn Write> = if path == "-" {
+        Box::new(stdout())
+    } else {
+        Box::new(fs::File::create(path)?)
+    };
+    Ok(writer)
+}
+
+/// 打开数据文件（`-` 表示标准输入），按文件头识别 gzip/zstd 压缩并边读边解压
+pub fn get_data_reader(path: &str) -> Result<Box<dyn Read>, anyhow::Error> {
+    let mut reader = BufReader::new(get_reader(path)?);
+    let head = reader.fill_buf()?;
+    let reader: Box<dyn Read> = if head.starts_with(GZIP_MAGIC) {
+        Box::new(flate2::bufread::MultiGzDecoder::new(reader))
+    } else if head.starts_with(ZSTD_MAGIC) {
+        Box::new(zstd::Decoder::with_buffer(reader)?)
+    } else {
+        Box::new(reader)
+    };
+    Ok(reader)
+}
+
+/// 创建数据文件（`-` 表示标准输出），`compression` 未指定时按 .gz/.zst 后缀决定是否压缩。
+/// 写完后必须调用 `DataWriter::finish` 写入压缩流结尾
+pub fn get_data_writer(
+    path: &str,
+    compression: Option<Compression>,
+) -> Result<DataWriter, anyhow::Error> {
+    let writer = get_writer(path)?;
+    let writer = match compression.or_else(|| Compression::from_path(path)) {
+        Some(Compression::Gzip) => DataWriter::Gzip(flate2::write::GzEncoder::new(
+            writer,
+            flate2::Compression::default(),
+        )),
+        Some(Compression::Zstd) => DataWriter::Zstd(zstd::Encoder::new(writer, 0)?),
+        None => DataWriter::Plain(writer),
+    };
+    Ok(writer)
+}
+
+/// 可能带压缩的输出
+pub enum DataWriter {
+    Plain(Box<dyn Write>),
+    Gzip(flate2::write::GzEncoder<Box<dyn Write>>),
+    Zstd(zstd::Encoder<'static, Box<dyn Write>>),
+}
+
+impl DataWriter {
+    /// 写入压缩流结尾并刷新输出，磁盘写满、管道断开等错误在这里返回
+    pub fn finish(self) -> Result<(), anyhow::Error> {
+        let mut writer = match self {
+            DataWriter::Plain(writer) => writer,
+            DataWriter::Gzip(encoder) => encoder.finish()?,
+            DataWriter::Zstd(encoder) => encoder.finish()?,
+        };
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+impl Write for DataWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self {
+            DataWriter::Plain(writer) => writer.write(buf),
+            DataWriter::Gzip(encoder) => encoder.write(buf),
+            DataWriter::Zstd(encoder) => encoder.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            DataWriter::Plain(writer) => writer.flush(),
+            DataWriter::Gzip(encoder) => encoder.flush(),
+            DataWriter::Zstd(encoder) => encoder.flush(),
+        }
+    }
+}
+
+/// 写到标准输出（`-`）时，下游提前关闭管道（例如 `| head -1`）视为正常结束
+pub fn ignore_broken_pipe<T: Default>(
+    path: &str,
+    result: Result<T, anyhow::Error>,
+) -> Result<T, anyhow::Error> {
+    match result {
+        Err(err) if path == "-" && is_broken_pipe(&err) => Ok(T::default()),
+        result => result,
+    }
+}
+
+fn is_broken_pipe(err: &anyhow::Error) -> bool {
+    err.chain().any(|cause| {
+        let kind = if let Some(err) = cause.downcast_ref::<std::io::Error>() {
+            Some(err.kind())
+        } else if let Some(err) = cause.downcast_ref::<csv::Error>() {
+            match err.kind() {
+                csv::ErrorKind::Io(err) => Some(err.kind()),
+                _ => None,
+            }
+        } else if let Some(err) = cause.downcast_ref::<serde_json::Error>() {
+            err.io_error_kind()
+        } else {
+            None
+        };
+        kind == Some(std::io::ErrorKind::BrokenPipe)
+    })
+}
+
 pub fn get_content(path: &str) -> Result<Vec<u8>, anyhow::Error> {
     let mut reader = get_reader(path)?;
     let mut buf = Vec::new();